@@ -1,8 +1,14 @@
 pub mod Card {
 
     use std::fmt;
+    use std::str::FromStr;
 
-    #[derive(Clone)]
+    use serde::{Deserialize, Serialize};
+
+    use crate::card_db::CardDB;
+    use crate::card_entry::CardEntry;
+
+    #[derive(Clone, Serialize, Deserialize)]
     pub struct AtomicCard {
         pub name: String,
         pub printings: Vec<String>,
@@ -18,59 +24,212 @@ pub mod Card {
     impl PartialEq for AtomicCard {
         fn eq(&self, other: &AtomicCard) -> bool {
             let mut digest: bool = self.name == other.name;
-            digest |= self.types == other.types;
+            digest &= self.types == other.types;
             // Due to issues with Cards keeping a reference to an Atomic, this isn't being checked
             //digest |= self.printings == other.printings;
             digest
         }
     }
 
-    #[derive(Clone)]
+    // The more flexible ways a want can describe the printings it will accept. The
+    // preferences (Oldest and Cheapest) accept any printing, but are used to rank the
+    // copies that match.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub enum PrintingPattern {
+        OneOf(Vec<String>),
+        Except(Vec<String>),
+        Oldest,
+        Cheapest,
+    }
+
+    impl PrintingPattern {
+        // A copy with an unknown printing is accepted, just like with exact printings.
+        pub fn accepts(&self, printing: &str) -> bool {
+            match self {
+                PrintingPattern::OneOf(sets) => printing.is_empty() || sets.iter().any(|s| s == printing),
+                PrintingPattern::Except(sets) => !sets.iter().any(|s| s == printing),
+                PrintingPattern::Oldest | PrintingPattern::Cheapest => true,
+            }
+        }
+
+        fn sets(&self) -> &[String] {
+            match self {
+                PrintingPattern::OneOf(sets) | PrintingPattern::Except(sets) => sets,
+                _ => &[],
+            }
+        }
+    }
+
+    impl FromStr for PrintingPattern {
+        type Err = ();
+
+        // Reads "M10|M11", "M10, M11", "!LEA,LEB", "except LEA, LEB", "oldest", and
+        // "cheapest". A single set code is not a pattern.
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let s = s.trim();
+            let lower = s.to_lowercase();
+            let split = |list: &str| -> Vec<String> {
+                list.split(['|', ',', ' '])
+                    .map(|c| c.trim().trim_start_matches('!').to_uppercase())
+                    .filter(|c| !c.is_empty())
+                    .collect()
+            };
+            if lower == "oldest" {
+                Ok(PrintingPattern::Oldest)
+            } else if lower == "cheapest" {
+                Ok(PrintingPattern::Cheapest)
+            } else if let Some(rest) = s.strip_prefix('!') {
+                Ok(PrintingPattern::Except(split(rest)))
+            } else if lower.starts_with("except ") {
+                Ok(PrintingPattern::Except(split(&s[7..])))
+            } else if s.contains(['|', ',']) {
+                Ok(PrintingPattern::OneOf(split(s)))
+            } else {
+                Err(())
+            }
+        }
+    }
+
+    impl fmt::Display for PrintingPattern {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                PrintingPattern::OneOf(sets) => write!(f, "{}", sets.join("|")),
+                PrintingPattern::Except(sets) => write!(f, "!{}", sets.join(",")),
+                PrintingPattern::Oldest => write!(f, "oldest"),
+                PrintingPattern::Cheapest => write!(f, "cheapest"),
+            }
+        }
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
     pub struct Card {
         pub card: AtomicCard,
         pub printing: String,
+        #[serde(default)]
+        pub pattern: Option<PrintingPattern>,
     }
 
+    // An empty printing means "any printing". Set codes are stored in upper case so that
+    // "[m11]" and "[M11]" refer to the same printing. If the printing is a pattern, such
+    // as "M10|M11", the pattern is stored and the printing is left empty.
     pub fn new(card: AtomicCard, printing: String) -> Card {
-        if card.printings.contains(&printing) {
-            Card {
+        match printing.parse::<PrintingPattern>() {
+            Ok(pattern) => Card {
                 card,
-                printing: printing,
-            }
-        } else {
-            Card {
+                printing: String::new(),
+                pattern: Some(pattern),
+            },
+            Err(_) => Card {
                 card,
-                printing: printing,
-            }
+                printing: printing.to_uppercase(),
+                pattern: None,
+            },
         }
     }
 
     impl Card {
         pub fn matches(&self, other: &Card) -> bool {
-            let mut digest: bool = self.card == other.card;
-            if (!self.printing.is_empty() && !other.printing.is_empty()) {
-                digest &= self.printing == other.printing;
+            self.card == other.card
+                && self.accepts(&other.printing)
+                && other.accepts(&self.printing)
+        }
+
+        // Checks whether a copy of the given printing is acceptable for this card.
+        fn accepts(&self, printing: &str) -> bool {
+            match &self.pattern {
+                Some(p) => p.accepts(printing),
+                None => self.printing.is_empty() || printing.is_empty() || self.printing == printing,
+            }
+        }
+
+        // Orders copies that match this card so that the preferred ones come first. Only
+        // the Oldest and Cheapest patterns have a preference; copies whose release date or
+        // price isn't known go last.
+        pub fn sort_by_preference(&self, db: &CardDB::CardDB, entries: &mut Vec<&CardEntry::CardEntry>) {
+            match self.pattern {
+                Some(PrintingPattern::Oldest) => {
+                    entries.sort_by_key(|e| {
+                        db.release_date(&e.card.printing)
+                            .cloned()
+                            .unwrap_or_else(|| String::from("9999"))
+                    });
+                }
+                Some(PrintingPattern::Cheapest) => {
+                    entries.sort_by(|a, b| {
+                        let a = db.price(&a.card.card.name, &a.card.printing).unwrap_or(f64::MAX);
+                        let b = db.price(&b.card.card.name, &b.card.printing).unwrap_or(f64::MAX);
+                        a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+                    });
+                }
+                _ => {}
             }
-            digest
         }
 
         pub fn get_name(&self) -> String {
             self.card.name.clone()
         }
-        
-        pub fn to_string( &self ) -> String {
-            if( self.printing.is_empty() )
-            {
-                format!( "{}", self.card.to_string() )
-            } else {
-                format!( "{} [{}]", self.card.to_string(), self.printing )
+
+        // Checks that the printing, and any sets that the pattern accepts, are ones that the
+        // card was actually printed in. Excluding a set that it wasn't printed in is harmless.
+        pub fn is_valid_printing(&self) -> bool {
+            let sets = match &self.pattern {
+                Some(p @ PrintingPattern::OneOf(_)) => p.sets(),
+                _ => &[],
+            };
+            (self.printing.is_empty() || self.card.printings.contains(&self.printing))
+                && sets.iter().all(|s| self.card.printings.contains(s))
+        }
+    }
+
+    impl fmt::Display for Card {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match &self.pattern {
+                Some(p) => write!(f, "{} [{}]", self.card, p),
+                None if self.printing.is_empty() => write!(f, "{}", self.card),
+                None => write!(f, "{} [{}]", self.card, self.printing),
             }
         }
     }
 
     impl PartialEq for Card {
         fn eq(&self, other: &Card) -> bool {
-            self.card == other.card && self.printing == other.printing
+            self.card == other.card && self.printing == other.printing && self.pattern == other.pattern
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn bolt(printing: &str) -> Card {
+            let card = AtomicCard { name: String::from("Lightning Bolt"), printings: vec![String::from("LEA"), String::from("M10"), String::from("M11")], types: vec![String::from("Instant")] };
+            new(card, printing.to_string())
+        }
+
+        #[test]
+        fn patterns_are_read_in_every_form() {
+            assert_eq!("M10|m11".parse::<PrintingPattern>(), Ok(PrintingPattern::OneOf(vec![String::from("M10"), String::from("M11")])));
+            assert_eq!("!LEA,LEB".parse::<PrintingPattern>(), Ok(PrintingPattern::Except(vec![String::from("LEA"), String::from("LEB")])));
+            assert_eq!("except lea".parse::<PrintingPattern>(), Ok(PrintingPattern::Except(vec![String::from("LEA")])));
+            assert_eq!("Cheapest".parse::<PrintingPattern>(), Ok(PrintingPattern::Cheapest));
+            assert_eq!("M11".parse::<PrintingPattern>(), Err(()));
+        }
+
+        #[test]
+        fn only_accepted_sets_have_to_exist() {
+            assert!(bolt("M10|M11").is_valid_printing());
+            assert!(!bolt("M10|THS").is_valid_printing());
+            assert!(bolt("!THS").is_valid_printing());
+            assert!(!bolt("THS").is_valid_printing());
+        }
+
+        #[test]
+        fn patterns_match_printings() {
+            assert!(bolt("M10|M11").matches(&bolt("M11")));
+            assert!(!bolt("M10|M11").matches(&bolt("LEA")));
+            assert!(!bolt("!LEA").matches(&bolt("LEA")));
+            assert!(bolt("!LEA").matches(&bolt("")));
+            assert!(bolt("oldest").matches(&bolt("LEA")));
         }
     }
 }
@@ -13,22 +13,99 @@ pub mod CardDB {
         data: HashMap<String, serde_json::Value>,
     }
 
+    #[derive(Serialize, Deserialize)]
+    struct RawSetList {
+        data: Vec<RawSet>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct RawSet {
+        code: String,
+        name: String,
+        release_date: String,
+    }
+
+    // Details of a card that are only used to sort, group and filter lists, so they are
+    // kept out of AtomicCard (which is stored with every entry).
+    #[derive(Clone, Default)]
+    pub struct CardDetails {
+        // Color letters in WUBRG order, e.g. ["R", "G"]. Colorless cards have none.
+        pub colors: Vec<String>,
+        pub mana_value: f64,
+    }
+
     pub struct CardDB {
         cards: HashMap<String, Card::AtomicCard>,
+        details: HashMap<String, CardDetails>,
+        // Set codes to release dates, e.g. "M11" to "2010-07-16"
+        sets: HashMap<String, String>,
+        // Lowercase set names to set codes, e.g. "magic 2011" to "M11"
+        set_codes: HashMap<String, String>,
+        // Set codes to set names, e.g. "M11" to "Magic 2011"
+        set_names: HashMap<String, String>,
+        // Card names to the price of each of their printings
+        prices: HashMap<String, HashMap<String, f64>>,
+        // Card names to the rarity of each of their printings
+        rarities: HashMap<String, HashMap<String, String>>,
     }
 
     pub fn new() -> CardDB {
         CardDB {
             cards: HashMap::new(),
+            details: HashMap::new(),
+            sets: HashMap::new(),
+            set_codes: HashMap::new(),
+            set_names: HashMap::new(),
+            prices: HashMap::new(),
+            rarities: HashMap::new(),
         }
     }
 
     pub fn create(filename: String) -> CardDB {
         let mut new_database: CardDB = new();
-        new_database.read_json(filename);
+        if let Err(why) = new_database.read_json(filename) {
+            println!("Could not parse the card database: {:?}", why);
+        }
+        if let Err(why) = new_database.read_sets(String::from("SetList.json")) {
+            println!("Could not read the set list, so release dates are unknown: {:?}", why);
+        }
+        if let Err(why) = new_database.read_prices(String::from("Prices.json")) {
+            println!("Could not read the price list, so prices are unknown: {:?}", why);
+        }
+        if let Err(why) = new_database.read_rarities(String::from("Rarities.json")) {
+            println!("Could not read the rarity list, so rarities are unknown: {:?}", why);
+        }
         new_database
     }
 
+    // A few real cards and sets for tests that need to look cards up.
+    #[cfg(test)]
+    pub fn sample() -> CardDB {
+        let mut digest = new();
+        let cards: [(&str, &[&str], &[&str]); 5] = [
+            ("Lightning Bolt", &["LEA", "M10", "M11", "STA"], &["Instant"]),
+            ("Duress", &["USG", "M19"], &["Sorcery"]),
+            ("Counterspell", &["LEA", "MH2"], &["Instant"]),
+            ("Quest for the Holy Relic", &["ZEN"], &["Enchantment"]),
+            ("Forest", &["LEA", "M11"], &["Land"]),
+        ];
+        for (name, printings, types) in cards {
+            let card = Card::AtomicCard {
+                name: name.to_string(),
+                printings: printings.iter().map(|p| p.to_string()).collect(),
+                types: types.iter().map(|t| t.to_string()).collect(),
+            };
+            digest.cards.insert(name.to_string(), card);
+        }
+        for (code, name, release_date) in [("LEA", "Limited Edition Alpha", "1993-08-05"), ("M10", "Magic 2010", "2009-07-17"), ("M11", "Magic 2011", "2010-07-16")] {
+            digest.set_codes.insert(name.to_lowercase(), code.to_string());
+            digest.set_names.insert(code.to_string(), name.to_string());
+            digest.sets.insert(code.to_string(), release_date.to_string());
+        }
+        digest
+    }
+
     impl TypeMapKey for CardDB {
         type Value = CardDB;
     }
@@ -38,6 +115,84 @@ pub mod CardDB {
             self.cards.get(card_name)
         }
 
+        pub fn release_date(&self, set: &str) -> Option<&String> {
+            self.sets.get(set)
+        }
+
+        pub fn set_code(&self, set_name: &str) -> Option<&String> {
+            self.set_codes.get(&set_name.to_lowercase())
+        }
+
+        pub fn set_name(&self, set: &str) -> Option<&String> {
+            self.set_names.get(set)
+        }
+
+        // Whether the optional price list was read, without which no printing is cheapest.
+        pub fn has_prices(&self) -> bool {
+            !self.prices.is_empty()
+        }
+
+        // Whether the optional set list was read, without which no printing is oldest.
+        pub fn has_release_dates(&self) -> bool {
+            !self.sets.is_empty()
+        }
+
+        pub fn price(&self, card_name: &str, set: &str) -> Option<f64> {
+            self.prices.get(card_name)?.get(set).copied()
+        }
+
+        pub fn details(&self, card_name: &str) -> Option<&CardDetails> {
+            self.details.get(card_name)
+        }
+
+        pub fn rarity(&self, card_name: &str, set: &str) -> Option<&String> {
+            self.rarities.get(card_name)?.get(set)
+        }
+
+        // Reads the set list from MTGJSON's SetList.json. This is optional data that is used
+        // to find the oldest printing of a card and to read set names in imported files.
+        pub fn read_sets(&mut self, filename: String) -> Result<()> {
+            let file_data = fs::read_to_string(filename).map_err(serde_json::Error::io)?;
+            let raw_sets: RawSetList = serde_json::from_str(&file_data)?;
+            for set in raw_sets.data {
+                self.set_codes.insert(set.name.to_lowercase(), set.code.to_uppercase());
+                self.set_names.insert(set.code.to_uppercase(), set.name);
+                self.sets.insert(set.code.to_uppercase(), set.release_date);
+            }
+            Ok(())
+        }
+
+        // Reads prices from a json object that maps card names to an object of set codes and
+        // prices, e.g. { "Lightning Bolt": { "M11": 1.25, "LEA": 450.0 } }. This is optional
+        // data that is only used to find the cheapest printing of a card.
+        pub fn read_prices(&mut self, filename: String) -> Result<()> {
+            let file_data = fs::read_to_string(filename).map_err(serde_json::Error::io)?;
+            let raw_prices: HashMap<String, HashMap<String, f64>> = serde_json::from_str(&file_data)?;
+            for (name, printings) in raw_prices {
+                let printings = printings
+                    .into_iter()
+                    .map(|(set, price)| (set.to_uppercase(), price))
+                    .collect();
+                self.prices.insert(name, printings);
+            }
+            Ok(())
+        }
+
+        // Reads rarities in the same shape as prices, e.g. { "Lightning Bolt": { "M11": "common" } }.
+        // This is optional data that is only used to group lists by rarity.
+        pub fn read_rarities(&mut self, filename: String) -> Result<()> {
+            let file_data = fs::read_to_string(filename).map_err(serde_json::Error::io)?;
+            let raw_rarities: HashMap<String, HashMap<String, String>> = serde_json::from_str(&file_data)?;
+            for (name, printings) in raw_rarities {
+                let printings = printings
+                    .into_iter()
+                    .map(|(set, rarity)| (set.to_uppercase(), rarity.to_lowercase()))
+                    .collect();
+                self.rarities.insert(name, printings);
+            }
+            Ok(())
+        }
+
         pub fn read_json(&mut self, filename: String) -> Result<()> {
             println!("Getting ready to read: {}", filename);
 
@@ -66,15 +221,31 @@ pub mod CardDB {
                 // Converts the json value struct to a list of strings
                 printings = Vec::new();
                 for p in data[0]["printings"].as_array().unwrap() {
-                    printings.push(p.to_string());
+                    if let Some(set) = p.as_str() {
+                        printings.push(set.to_uppercase());
+                    }
                 }
 
                 // Converts the json value struct to a list of strings
                 types = Vec::new();
                 for tp in data[0]["types"].as_array().unwrap() {
-                    types.push(tp.to_string());
+                    if let Some(t) = tp.as_str() {
+                        types.push(t.to_string());
+                    }
                 }
 
+                // Colors and mana value are optional, since they are only used to sort lists
+                let mut colors: Vec<String> = data[0]["colors"]
+                    .as_array()
+                    .map(|c| c.iter().filter_map(|c| c.as_str()).map(String::from).collect())
+                    .unwrap_or_default();
+                colors.sort_by_key(|c| "WUBRG".find(c.as_str()));
+                let mana_value = data[0]["manaValue"]
+                    .as_f64()
+                    .or_else(|| data[0]["convertedManaCost"].as_f64())
+                    .unwrap_or_default();
+                self.details.insert(name.clone(), CardDetails { colors, mana_value });
+
                 // Adds a card to the HashMap index by the card's name
                 self.cards.insert(
                     name.clone(),
@@ -1,37 +1,415 @@
 pub mod CardEntry {
 
+    use std::fmt;
+    use std::str::FromStr;
+
+    use serde::{Deserialize, Serialize};
+
     use crate::card::Card;
+    use crate::collectible::Collectible;
     use crate::utils::Types::*;
 
-    #[derive( Clone )]
+    // Conditions are ordered from worst to best so that "at least LP" is a simple comparison.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+    pub enum Condition {
+        Damaged,
+        HeavilyPlayed,
+        ModeratelyPlayed,
+        LightlyPlayed,
+        NearMint,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub enum Finish {
+        Nonfoil,
+        Foil,
+        Etched,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub enum Language {
+        English,
+        Japanese,
+        German,
+        French,
+        Italian,
+        Spanish,
+        Portuguese,
+        Russian,
+        Korean,
+        SimplifiedChinese,
+        TraditionalChinese,
+        Phyrexian,
+    }
+
+    impl FromStr for Condition {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            // Collection managers spell these out in several ways, e.g. "near_mint" or
+            // "Good (Lightly Played)".
+            match s.trim().to_uppercase().replace('_', " ").as_str() {
+                "NM" | "MINT" | "NEARMINT" | "NEAR MINT" => Ok(Condition::NearMint),
+                "LP" | "SP" | "EX" | "LIGHTLYPLAYED" | "LIGHTLY PLAYED" | "GOOD" | "GOOD (LIGHTLY PLAYED)" | "EXCELLENT" => Ok(Condition::LightlyPlayed),
+                "MP" | "MODERATELYPLAYED" | "MODERATELY PLAYED" | "PLAYED" => Ok(Condition::ModeratelyPlayed),
+                "HP" | "HEAVILYPLAYED" | "HEAVILY PLAYED" => Ok(Condition::HeavilyPlayed),
+                "DMG" | "PO" | "DAMAGED" | "POOR" => Ok(Condition::Damaged),
+                _ => Err(()),
+            }
+        }
+    }
+
+    impl fmt::Display for Condition {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let code = match self {
+                Condition::NearMint => "NM",
+                Condition::LightlyPlayed => "LP",
+                Condition::ModeratelyPlayed => "MP",
+                Condition::HeavilyPlayed => "HP",
+                Condition::Damaged => "DMG",
+            };
+            write!(f, "{}", code)
+        }
+    }
+
+    impl Condition {
+        pub fn name(&self) -> &'static str {
+            match self {
+                Condition::NearMint => "Near Mint",
+                Condition::LightlyPlayed => "Lightly Played",
+                Condition::ModeratelyPlayed => "Moderately Played",
+                Condition::HeavilyPlayed => "Heavily Played",
+                Condition::Damaged => "Damaged",
+            }
+        }
+    }
+
+    impl FromStr for Finish {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.trim().to_lowercase().as_str() {
+                "nonfoil" | "non-foil" | "normal" => Ok(Finish::Nonfoil),
+                "foil" => Ok(Finish::Foil),
+                "etched" | "etched foil" => Ok(Finish::Etched),
+                _ => Err(()),
+            }
+        }
+    }
+
+    impl fmt::Display for Finish {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let name = match self {
+                Finish::Nonfoil => "nonfoil",
+                Finish::Foil => "foil",
+                Finish::Etched => "etched",
+            };
+            write!(f, "{}", name)
+        }
+    }
+
+    impl FromStr for Language {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.trim().to_uppercase().as_str() {
+                "EN" | "ENG" | "ENGLISH" => Ok(Language::English),
+                "JP" | "JA" | "JPN" | "JAPANESE" => Ok(Language::Japanese),
+                "DE" | "GER" | "GERMAN" => Ok(Language::German),
+                "FR" | "FRA" | "FRENCH" => Ok(Language::French),
+                "IT" | "ITA" | "ITALIAN" => Ok(Language::Italian),
+                "ES" | "SPA" | "SPANISH" => Ok(Language::Spanish),
+                "PT" | "POR" | "PORTUGUESE" | "PORTUGUESE (BRAZIL)" => Ok(Language::Portuguese),
+                "RU" | "RUS" | "RUSSIAN" => Ok(Language::Russian),
+                "KO" | "KR" | "KOR" | "KOREAN" => Ok(Language::Korean),
+                "ZHS" | "CS" | "CHINESE SIMPLIFIED" | "SIMPLIFIED CHINESE" => Ok(Language::SimplifiedChinese),
+                "ZHT" | "CT" | "CHINESE TRADITIONAL" | "TRADITIONAL CHINESE" => Ok(Language::TraditionalChinese),
+                "PH" | "PHYREXIAN" => Ok(Language::Phyrexian),
+                _ => Err(()),
+            }
+        }
+    }
+
+    impl Language {
+        pub fn name(&self) -> &'static str {
+            match self {
+                Language::English => "English",
+                Language::Japanese => "Japanese",
+                Language::German => "German",
+                Language::French => "French",
+                Language::Italian => "Italian",
+                Language::Spanish => "Spanish",
+                Language::Portuguese => "Portuguese",
+                Language::Russian => "Russian",
+                Language::Korean => "Korean",
+                Language::SimplifiedChinese => "Chinese Simplified",
+                Language::TraditionalChinese => "Chinese Traditional",
+                Language::Phyrexian => "Phyrexian",
+            }
+        }
+    }
+
+    impl fmt::Display for Language {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let code = match self {
+                Language::English => "EN",
+                Language::Japanese => "JP",
+                Language::German => "DE",
+                Language::French => "FR",
+                Language::Italian => "IT",
+                Language::Spanish => "ES",
+                Language::Portuguese => "PT",
+                Language::Russian => "RU",
+                Language::Korean => "KO",
+                Language::SimplifiedChinese => "ZHS",
+                Language::TraditionalChinese => "ZHT",
+                Language::Phyrexian => "PH",
+            };
+            write!(f, "{}", code)
+        }
+    }
+
+    pub fn is_attribute(token: &str) -> bool {
+        token.parse::<Condition>().is_ok()
+            || token.parse::<Finish>().is_ok()
+            || token.parse::<Language>().is_ok()
+            || Collectible::is_attribute(token)
+    }
+
+    // Like an empty printing, an attribute that is None means "unspecified". For a want,
+    // that means any value is acceptable. For a have, the usual default (NM, nonfoil,
+    // English) is assumed when checking it against a want. Ordinary copies have no
+    // collectible details.
+    #[derive(Clone, Serialize, Deserialize)]
     pub struct CardEntry {
         pub count: CardCount,
-        pub card: Card::Card
+        pub card: Card::Card,
+        #[serde(default)]
+        pub condition: Option<Condition>,
+        #[serde(default)]
+        pub finish: Option<Finish>,
+        #[serde(default)]
+        pub language: Option<Language>,
+        #[serde(default)]
+        pub collectible: Option<Collectible::Collectible>,
+        // Copies held by open or accepted trade proposals. They stay in the list, but can't
+        // be matched, offered again or removed until the proposal is closed.
+        #[serde(default, skip_serializing_if = "is_zero")]
+        pub reserved: CardCount,
+    }
+
+    fn is_zero(count: &CardCount) -> bool {
+        *count == 0
     }
 
     pub fn new(count: CardCount, card: Card::Card) -> CardEntry {
-        CardEntry { count, card }
+        CardEntry { count, card, condition: None, finish: None, language: None, collectible: None, reserved: 0 }
     }
-    
+
     impl CardEntry {
-        pub fn inc_count( &mut self, count: &CardCount ) {
-            self.count += count;
+        // Two entries are the same listing if they describe interchangeable copies, i.e.
+        // they should be stored as a single entry with a combined count.
+        pub fn same_listing(&self, other: &CardEntry) -> bool {
+            self.card == other.card
+                && self.condition == other.condition
+                && self.finish == other.finish
+                && self.language == other.language
+                && self.collectible == other.collectible
+        }
+
+        // Checks if every detail given by this entry is shared by the other entry. Used to
+        // pick which copies a request like "remove 2 Thoughtseize foil" refers to.
+        pub fn covers(&self, other: &CardEntry) -> bool {
+            self.card.card == other.card.card
+                && (self.card.printing.is_empty() || self.card.printing == other.card.printing)
+                && self.condition.map_or(true, |c| other.condition == Some(c))
+                && self.finish.map_or(true, |f| other.finish == Some(f))
+                && self.language.map_or(true, |l| other.language == Some(l))
+                && match (self.collectible(), other.collectible()) {
+                    (None, None) => true,
+                    (Some(s), Some(o)) => o.satisfies(s),
+                    _ => false,
+                }
         }
 
-        pub fn dec_count(&mut self, count: &CardCount) {
-            if self.count >= *count {
-                self.count -= count;
+        // Checks if this entry, as a have, is acceptable for the given want. The want's
+        // condition is treated as a minimum. Collectibles only match wants that ask for
+        // them, so an ordinary want never turns up a graded or signed copy.
+        pub fn satisfies(&self, want: &CardEntry) -> bool {
+            self.satisfies_basics(want)
+                && match (self.collectible(), want.collectible()) {
+                    (None, None) => true,
+                    (Some(h), Some(w)) => h.satisfies(w),
+                    _ => false,
+                }
+        }
+
+        // Like satisfies, but a query without collectible details finds every copy.
+        pub fn fits_search(&self, query: &CardEntry) -> bool {
+            self.satisfies_basics(query)
+                && match (self.collectible(), query.collectible()) {
+                    (_, None) => true,
+                    (Some(h), Some(q)) => h.satisfies(q),
+                    (None, Some(_)) => false,
+                }
+        }
+
+        // The collectible details that matter for matching. A copy with nothing but a note
+        // is matched like any ordinary copy.
+        pub fn collectible(&self) -> Option<&Collectible::Collectible> {
+            self.collectible.as_ref().filter(|c| !c.is_ordinary())
+        }
+
+        fn satisfies_basics(&self, want: &CardEntry) -> bool {
+            self.card.matches(&want.card)
+                && want.condition.map_or(true, |c| self.get_condition() >= c)
+                && want.finish.map_or(true, |f| self.get_finish() == f)
+                && want.language.map_or(true, |l| self.get_language() == l)
+        }
+
+        pub fn get_condition(&self) -> Condition {
+            self.condition.unwrap_or(Condition::NearMint)
+        }
+
+        pub fn get_finish(&self) -> Finish {
+            self.finish.unwrap_or(Finish::Nonfoil)
+        }
+
+        pub fn get_language(&self) -> Language {
+            self.language.unwrap_or(Language::English)
+        }
+
+        // Tries to read the token as a condition, finish, language, or collectible detail
+        // and sets it if it is one. Returns false if the token isn't any of them.
+        pub fn set_attribute(&mut self, token: &str) -> bool {
+            if let Ok(f) = token.parse::<Finish>() {
+                self.finish = Some(f);
+            } else if let Ok(c) = token.parse::<Condition>() {
+                self.condition = Some(c);
+            } else if let Ok(l) = token.parse::<Language>() {
+                self.language = Some(l);
+            } else if Collectible::is_attribute(token) {
+                self.collectible
+                    .get_or_insert_with(Collectible::new)
+                    .set_attribute(token);
             } else {
-                self.count = 0;
+                return false;
             }
+            true
+        }
+
+        pub fn set_note(&mut self, note: String) {
+            self.collectible.get_or_insert_with(Collectible::new).note = Some(note);
+        }
+
+        pub fn inc_count( &mut self, count: &CardCount ) {
+            self.count += count;
+        }
+
+        // Decreases the count by as much as possible and returns how many were actually removed.
+        // Copies held by trade proposals stay until the proposal lets go of them.
+        pub fn dec_count(&mut self, count: &CardCount) -> CardCount {
+            let removed = std::cmp::min(self.available(), *count);
+            self.count -= removed;
+            removed
+        }
+
+        // Copies that aren't held by a trade proposal.
+        pub fn available(&self) -> CardCount {
+            self.count - self.reserved
+        }
+
+        // Holds up to count more copies and returns how many were held.
+        pub fn reserve(&mut self, count: CardCount) -> CardCount {
+            let held = std::cmp::min(self.available(), count);
+            self.reserved += held;
+            held
+        }
+
+        // Lets go of up to count held copies and returns how many were let go.
+        pub fn release(&mut self, count: CardCount) -> CardCount {
+            let released = std::cmp::min(self.reserved, count);
+            self.reserved -= released;
+            released
         }
 
         pub fn update_count(&mut self, count: CardCount) {
             self.count = count;
         }
-        
-        pub fn to_string( &self ) -> String {
-            format!( "{} {}", self.count, self.card.to_string()  )
+    }
+
+    impl fmt::Display for CardEntry {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{} {}", self.count, self.card)?;
+            if let Some(finish) = self.finish {
+                write!(f, " {}", finish)?;
+            }
+            if let Some(condition) = self.condition {
+                write!(f, " {}", condition)?;
+            }
+            if let Some(language) = self.language {
+                write!(f, " {}", language)?;
+            }
+            if let Some(collectible) = &self.collectible {
+                write!(f, " {}", collectible)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn bolt(printing: &str, attributes: &[&str]) -> CardEntry {
+            let card = Card::AtomicCard { name: String::from("Lightning Bolt"), printings: vec![String::from("M10"), String::from("M11")], types: vec![String::from("Instant")] };
+            let mut digest = new(1, Card::new(card, printing.to_string()));
+            for attribute in attributes {
+                assert!(digest.set_attribute(attribute));
+            }
+            digest
+        }
+
+        #[test]
+        fn attributes_are_read_in_any_spelling() {
+            assert_eq!("Good (Lightly Played)".parse::<Condition>(), Ok(Condition::LightlyPlayed));
+            assert_eq!("near_mint".parse::<Condition>(), Ok(Condition::NearMint));
+            assert_eq!("Etched Foil".parse::<Finish>(), Ok(Finish::Etched));
+            assert_eq!("jp".parse::<Language>(), Ok(Language::Japanese));
+            assert!(!is_attribute("Bolt"));
+        }
+
+        #[test]
+        fn wanted_condition_is_a_minimum() {
+            let want = bolt("", &["LP"]);
+            assert!(bolt("", &["NM"]).satisfies(&want));
+            assert!(bolt("", &["LP"]).satisfies(&want));
+            assert!(!bolt("", &["MP"]).satisfies(&want));
+            // Haves without a condition are taken to be near mint
+            assert!(bolt("", &[]).satisfies(&want));
+        }
+
+        #[test]
+        fn unspecified_want_details_accept_anything() {
+            let want = bolt("", &[]);
+            assert!(bolt("M11", &["foil", "DMG", "JP"]).satisfies(&want));
+            assert!(!bolt("M11", &[]).satisfies(&bolt("M10", &[])));
+            assert!(bolt("M11", &[]).satisfies(&bolt("M11", &[])));
+        }
+
+        #[test]
+        fn have_defaults_are_nonfoil_english() {
+            assert!(bolt("", &[]).satisfies(&bolt("", &["nonfoil", "EN"])));
+            assert!(!bolt("", &[]).satisfies(&bolt("", &["foil"])));
+            assert!(!bolt("", &["foil"]).satisfies(&bolt("", &["nonfoil"])));
+            assert!(!bolt("", &["DE"]).satisfies(&bolt("", &["EN"])));
+        }
+
+        #[test]
+        fn same_listing_needs_every_detail() {
+            assert!(bolt("M11", &["foil"]).same_listing(&bolt("m11", &["foil"])));
+            assert!(!bolt("M11", &["foil"]).same_listing(&bolt("M11", &[])));
+            assert!(!bolt("M11", &["NM"]).same_listing(&bolt("M11", &[])));
         }
     }
 }
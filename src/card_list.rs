@@ -0,0 +1,280 @@
+pub mod CardList {
+
+    use std::collections::BTreeMap;
+    use std::fmt;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::card::Card;
+    use crate::card_entry::CardEntry;
+    use crate::utils::Types::*;
+
+    // The storage that backs a tradelist. Entries are grouped by card name, in name order
+    // so that lists always print the same way, and each group holds at most one entry per
+    // distinct listing (see CardEntry::same_listing). All mutation goes through add and
+    // remove, which call prune to make sure that no entry has a count of zero and no name
+    // maps to an empty group.
+    #[derive(Clone, Default, Serialize, Deserialize)]
+    pub struct CardList {
+        entries: BTreeMap<String, Vec<CardEntry::CardEntry>>,
+    }
+
+    pub fn new() -> CardList {
+        CardList { entries: BTreeMap::new() }
+    }
+
+    impl CardList {
+        pub fn add(&mut self, entry: CardEntry::CardEntry) {
+            if entry.count == 0 {
+                return;
+            }
+            let listing = self.entries.entry(entry.card.get_name()).or_default();
+            match listing.iter_mut().find(|c| c.same_listing(&entry)) {
+                Some(c) => {
+                    c.inc_count(&entry.count);
+                    c.reserved += entry.reserved;
+                }
+                None => listing.push(entry),
+            }
+        }
+
+        // Removes up to entry.count copies of the card and returns how many were removed.
+        // The exact listing is used first, then any other copies that have every detail
+        // the entry gives, e.g. "Thoughtseize [THS]" can remove foil and nonfoil copies.
+        pub fn remove(&mut self, entry: &CardEntry::CardEntry) -> CardCount {
+            self.take(entry).iter().map(|c| c.count).sum()
+        }
+
+        // Removes copies the same way as remove, but returns the copies that were removed
+        // with all of their details so they can be added somewhere else. Copies held by
+        // trade proposals are never taken.
+        pub fn take(&mut self, entry: &CardEntry::CardEntry) -> Vec<CardEntry::CardEntry> {
+            let name = entry.card.get_name();
+            let listing = match self.entries.get_mut(&name) {
+                Some(listing) => listing,
+                None => return Vec::new(),
+            };
+            let mut digest = Vec::new();
+            let mut remaining: CardCount = entry.count;
+            for exact in [true, false] {
+                for c in listing.iter_mut() {
+                    if remaining == 0 {
+                        break;
+                    }
+                    let fits = if exact { c.same_listing(entry) } else { entry.covers(c) };
+                    if fits && c.available() > 0 {
+                        let count = c.dec_count(&remaining);
+                        remaining -= count;
+                        let mut taken = c.clone();
+                        taken.update_count(count);
+                        taken.reserved = 0;
+                        digest.push(taken);
+                    }
+                }
+            }
+            self.prune(&name);
+            digest
+        }
+
+        pub fn contains(&self, card: &Card::Card) -> bool {
+            self.get(&card.get_name()).any(|c| c.card.matches(card))
+        }
+
+        // All entries that are acceptable for the given want and have copies that aren't
+        // held by a trade proposal.
+        pub fn matching<'a>(&'a self, want: &'a CardEntry::CardEntry) -> impl Iterator<Item = &'a CardEntry::CardEntry> {
+            self.get(&want.card.get_name()).filter(move |c| c.available() > 0 && c.satisfies(want))
+        }
+
+        // Holds up to count copies that are acceptable for the want. Returns how many were held.
+        pub fn reserve(&mut self, want: &CardEntry::CardEntry, count: CardCount) -> CardCount {
+            let mut held = 0;
+            for c in self.entries.get_mut(&want.card.get_name()).into_iter().flatten() {
+                if c.satisfies(want) {
+                    held += c.reserve(count - held);
+                }
+            }
+            held
+        }
+
+        // Lets go of up to count held copies that are acceptable for the want. Returns how
+        // many were let go.
+        pub fn release(&mut self, want: &CardEntry::CardEntry, count: CardCount) -> CardCount {
+            let mut released = 0;
+            for c in self.entries.get_mut(&want.card.get_name()).into_iter().flatten() {
+                if c.satisfies(want) {
+                    released += c.release(count - released);
+                }
+            }
+            released
+        }
+
+        // Keeps the holds from another version of this list on the copies that are still here.
+        // Returns the held copies that aren't, whose holds are lost.
+        pub fn copy_reservations(&mut self, other: &CardList) -> Vec<CardEntry::CardEntry> {
+            let mut digest = Vec::new();
+            for old in other.iter().filter(|c| c.reserved > 0) {
+                let mut kept = 0;
+                for c in self.entries.get_mut(&old.card.get_name()).into_iter().flatten() {
+                    if c.same_listing(old) {
+                        c.reserved = std::cmp::min(old.reserved, c.count);
+                        kept = c.reserved;
+                    }
+                }
+                if kept < old.reserved {
+                    let mut lost = old.clone();
+                    lost.update_count(old.reserved - kept);
+                    lost.reserved = 0;
+                    digest.push(lost);
+                }
+            }
+            digest
+        }
+
+        // Copies held by trade proposals.
+        pub fn reserved_count(&self) -> CardCount {
+            self.iter().map(|c| c.reserved).sum()
+        }
+
+        // The copies in this list that weren't in the other version of it, including extra
+        // copies of listings that were.
+        pub fn added_since(&self, before: &CardList) -> Vec<CardEntry::CardEntry> {
+            let mut digest = Vec::new();
+            for entry in self.iter() {
+                let had: CardCount = before.get(&entry.card.get_name()).filter(|c| c.same_listing(entry)).map(|c| c.count).sum();
+                if entry.count > had {
+                    let mut added = entry.clone();
+                    added.update_count(entry.count - had);
+                    added.reserved = 0;
+                    digest.push(added);
+                }
+            }
+            digest
+        }
+
+        // All entries for the given card name.
+        pub fn get(&self, name: &str) -> impl Iterator<Item = &CardEntry::CardEntry> {
+            self.entries.get(name).into_iter().flatten()
+        }
+
+        pub fn iter(&self) -> impl Iterator<Item = &CardEntry::CardEntry> {
+            self.entries.values().flatten()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.entries.is_empty()
+        }
+
+        // Total number of copies across all entries.
+        pub fn card_count(&self) -> CardCount {
+            self.iter().map(|c| c.count).sum()
+        }
+
+        fn prune(&mut self, name: &str) {
+            if let Some(listing) = self.entries.get_mut(name) {
+                listing.retain(|c| c.count > 0);
+                if listing.is_empty() {
+                    self.entries.remove(name);
+                }
+            }
+        }
+    }
+
+    impl fmt::Display for CardList {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            for c in self.iter() {
+                writeln!(f, "{}", c)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::card_entry::CardEntry::Finish;
+
+        fn entry(count: CardCount, printing: &str) -> CardEntry::CardEntry {
+            let bolt = Card::AtomicCard { name: String::from("Lightning Bolt"), printings: vec![String::from("M10"), String::from("M11")], types: vec![String::from("Instant")] };
+            CardEntry::new(count, Card::new(bolt, printing.to_string()))
+        }
+
+        fn foil(count: CardCount, printing: &str) -> CardEntry::CardEntry {
+            let mut digest = entry(count, printing);
+            digest.finish = Some(Finish::Foil);
+            digest
+        }
+
+        #[test]
+        fn same_listing_is_stored_once() {
+            let mut list = new();
+            list.add(entry(4, "M11"));
+            list.add(entry(2, "m11"));
+            list.add(entry(0, "M10"));
+            assert_eq!(list.iter().count(), 1);
+            assert_eq!(list.card_count(), 6);
+            list.add(foil(1, "M11"));
+            assert_eq!(list.iter().count(), 2);
+        }
+
+        #[test]
+        fn removing_every_copy_leaves_nothing_behind() {
+            let mut list = new();
+            list.add(entry(2, "M11"));
+            assert_eq!(list.remove(&entry(5, "M11")), 2);
+            assert!(list.is_empty());
+            assert_eq!(list.get("Lightning Bolt").count(), 0);
+        }
+
+        #[test]
+        fn take_prefers_the_exact_listing() {
+            let mut list = new();
+            list.add(foil(2, ""));
+            list.add(entry(2, ""));
+            let taken = list.take(&entry(3, ""));
+            assert_eq!(taken.iter().map(|c| c.count).sum::<CardCount>(), 3);
+            assert_eq!(taken[0].finish, None);
+            assert_eq!(taken[0].count, 2);
+            assert_eq!(list.card_count(), 1);
+            assert_eq!(list.iter().next().unwrap().finish, Some(Finish::Foil));
+        }
+
+        #[test]
+        fn take_only_touches_the_printing_it_names() {
+            let mut list = new();
+            list.add(entry(2, "M10"));
+            list.add(entry(2, ""));
+            assert_eq!(list.remove(&entry(3, "M11")), 0);
+            assert_eq!(list.remove(&entry(3, "M10")), 2);
+            assert_eq!(list.card_count(), 2);
+        }
+
+        #[test]
+        fn held_copies_stay_within_the_count() {
+            let mut list = new();
+            list.add(entry(3, "M11"));
+            assert_eq!(list.reserve(&entry(1, ""), 5), 3);
+            assert!(list.iter().all(|c| c.reserved <= c.count));
+            assert_eq!(list.matching(&entry(1, "")).count(), 0);
+            assert_eq!(list.remove(&entry(3, "M11")), 0);
+            assert_eq!(list.release(&entry(1, ""), 2), 2);
+            assert_eq!(list.remove(&entry(3, "M11")), 2);
+            assert!(list.iter().all(|c| c.reserved <= c.count));
+            assert_eq!(list.card_count(), 1);
+            assert_eq!(list.release(&entry(1, ""), 5), 1);
+        }
+
+        #[test]
+        fn reservations_carry_over_to_a_smaller_list() {
+            let mut old = new();
+            old.add(entry(4, "M11"));
+            old.reserve(&entry(1, "M11"), 3);
+            let mut smaller = new();
+            smaller.add(entry(2, "M11"));
+            let lost = smaller.copy_reservations(&old);
+            assert_eq!(smaller.iter().next().unwrap().reserved, 2);
+            assert_eq!(lost.iter().map(|c| (c.count, c.reserved)).collect::<Vec<_>>(), vec![(1, 0)]);
+            assert_eq!(new().copy_reservations(&old)[0].count, 3);
+        }
+    }
+}
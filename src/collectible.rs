@@ -0,0 +1,155 @@
+pub mod Collectible {
+
+    use std::fmt;
+    use std::str::FromStr;
+
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub enum GradingCompany {
+        PSA,
+        BGS,
+        CGC,
+        SGC,
+    }
+
+    impl FromStr for GradingCompany {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.to_uppercase().as_str() {
+                "PSA" => Ok(GradingCompany::PSA),
+                "BGS" | "BECKETT" => Ok(GradingCompany::BGS),
+                "CGC" => Ok(GradingCompany::CGC),
+                "SGC" => Ok(GradingCompany::SGC),
+                _ => Err(()),
+            }
+        }
+    }
+
+    impl fmt::Display for GradingCompany {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{:?}", self)
+        }
+    }
+
+    // A grade like "PSA 9.5". The score is kept in tenths so that half grades can be
+    // compared exactly. When searching, a company of None and a score of 0 means "any".
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub struct Grade {
+        pub company: Option<GradingCompany>,
+        pub score: u8,
+    }
+
+    impl Grade {
+        pub fn satisfies(&self, want: &Grade) -> bool {
+            want.company.map_or(true, |c| self.company == Some(c)) && self.score >= want.score
+        }
+    }
+
+    impl FromStr for Grade {
+        type Err = ();
+
+        // Reads "PSA 9", "BGS 9.5", etc.
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let mut parts = s.split_whitespace();
+            let company = parts.next().ok_or(())?.parse::<GradingCompany>()?;
+            let score = parts.next().ok_or(())?.parse::<f32>().map_err(|_| ())?;
+            if parts.next().is_some() || !(1.0..=10.0).contains(&score) {
+                return Err(());
+            }
+            Ok(Grade { company: Some(company), score: (score * 10.0).round() as u8 })
+        }
+    }
+
+    impl fmt::Display for Grade {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self.company {
+                None => write!(f, "graded"),
+                Some(c) if self.score % 10 == 0 => write!(f, "{} {}", c, self.score / 10),
+                Some(c) => write!(f, "{} {}.{}", c, self.score / 10, self.score % 10),
+            }
+        }
+    }
+
+    // The details that make a copy more than an ordinary copy of a card. Entries without
+    // any of these have no Collectible at all, which keeps ordinary copies and collectibles
+    // from being mixed up during matching. A free-text note is kept here too, but on its
+    // own it doesn't make a copy a collectible.
+    #[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub struct Collectible {
+        pub grade: Option<Grade>,
+        pub signed: bool,
+        pub altered: bool,
+        pub misprint: bool,
+        pub note: Option<String>,
+    }
+
+    pub fn new() -> Collectible {
+        Collectible::default()
+    }
+
+    pub fn is_attribute(token: &str) -> bool {
+        matches!(token.to_lowercase().as_str(), "signed" | "altered" | "misprint" | "graded")
+            || token.parse::<Grade>().is_ok()
+    }
+
+    impl Collectible {
+        // True if there is nothing but a note, which makes a copy no different from an
+        // ordinary one.
+        pub fn is_ordinary(&self) -> bool {
+            self.grade.is_none() && !self.signed && !self.altered && !self.misprint
+        }
+
+        // Tries to read the token as a collectible attribute and sets it if it is one.
+        pub fn set_attribute(&mut self, token: &str) -> bool {
+            match token.to_lowercase().as_str() {
+                "signed" => self.signed = true,
+                "altered" => self.altered = true,
+                "misprint" => self.misprint = true,
+                "graded" => self.grade = Some(Grade { company: None, score: 0 }),
+                _ => match token.parse::<Grade>() {
+                    Ok(g) => self.grade = Some(g),
+                    Err(_) => return false,
+                },
+            }
+            true
+        }
+
+        // Checks if this copy has everything the want asks for. Notes are free text and
+        // are never used for matching.
+        pub fn satisfies(&self, want: &Collectible) -> bool {
+            let grade_ok = match (&self.grade, &want.grade) {
+                (_, None) => true,
+                (None, Some(_)) => false,
+                (Some(g), Some(w)) => g.satisfies(w),
+            };
+            grade_ok
+                && (self.signed || !want.signed)
+                && (self.altered || !want.altered)
+                && (self.misprint || !want.misprint)
+        }
+    }
+
+    impl fmt::Display for Collectible {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let mut parts: Vec<String> = Vec::new();
+            if let Some(g) = self.grade {
+                parts.push(g.to_string());
+            }
+            if self.signed {
+                parts.push(String::from("signed"));
+            }
+            if self.altered {
+                parts.push(String::from("altered"));
+            }
+            if self.misprint {
+                parts.push(String::from("misprint"));
+            }
+            if let Some(n) = &self.note {
+                parts.push(format!("\"{}\"", n));
+            }
+            write!(f, "{}", parts.join(" "))
+        }
+    }
+}
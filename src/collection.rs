@@ -0,0 +1,296 @@
+pub mod Collection {
+
+    use std::fmt;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::card::Card;
+    use crate::card_db::CardDB;
+    use crate::card_entry::CardEntry;
+    use crate::card_list::CardList;
+    use crate::utils::Types::*;
+
+    // What a keep rule applies to. More specific targets win over less specific ones.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum Target {
+        Everything,
+        Type(String),
+        Card(String),
+    }
+
+    // A rule like "keep 4 of every card" or "keep 1 for Commander". Rules with different
+    // purposes add up, so keeping 4 and keeping 1 for Commander keeps 5. Within a purpose
+    // only the most specific rule counts, so keeping 4 and keeping 8 Lightning Bolt keeps 8
+    // Bolts and 4 of everything else.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct KeepRule {
+        pub count: CardCount,
+        pub target: Target,
+        pub purpose: Option<String>,
+    }
+
+    impl KeepRule {
+        fn applies(&self, card: &Card::AtomicCard) -> bool {
+            match &self.target {
+                Target::Everything => true,
+                Target::Type(t) => card.types.iter().any(|c| c.eq_ignore_ascii_case(t)),
+                Target::Card(name) => card.name == *name,
+            }
+        }
+
+        fn specificity(&self) -> u8 {
+            match self.target {
+                Target::Everything => 0,
+                Target::Type(_) => 1,
+                Target::Card(_) => 2,
+            }
+        }
+
+        // Purposes are typed by hand, so "commander" and "Commander" are the same purpose.
+        fn same_purpose(&self, other: &KeepRule) -> bool {
+            match (&self.purpose, &other.purpose) {
+                (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+                (None, None) => true,
+                _ => false,
+            }
+        }
+
+        fn same_scope(&self, other: &KeepRule) -> bool {
+            self.target == other.target && self.same_purpose(other)
+        }
+    }
+
+    // Reads rules like "4", "1 for Commander", "8 Lightning Bolt" or "0 type:Land for Cube".
+    // Card names can contain " for " too (e.g. "Quest for the Holy Relic"), so the purpose
+    // is only split off where the rest still names a card.
+    pub fn parse_rule(db: &CardDB::CardDB, text: &str) -> Result<KeepRule, String> {
+        let text = text.trim();
+        let mut splits: Vec<(&str, Option<String>)> = vec![(text, None)];
+        for (i, _) in text.to_ascii_lowercase().rmatch_indices(" for ") {
+            splits.push((text[..i].trim(), Some(text[i + 5..].trim().to_string()).filter(|p| !p.is_empty())));
+        }
+        // If nothing fits, the error is the one for the last " for ", as that is how most
+        // rules are written.
+        let mut error = None;
+        for (i, (rule, purpose)) in splits.iter().enumerate() {
+            match parse_count_and_target(db, rule) {
+                Ok((count, target)) => return Ok(KeepRule { count, target, purpose: purpose.clone() }),
+                Err(why) if i == 1 || error.is_none() => error = Some(why),
+                Err(_) => {}
+            }
+        }
+        Err(error.unwrap_or_default())
+    }
+
+    fn parse_count_and_target(db: &CardDB::CardDB, text: &str) -> Result<(CardCount, Target), String> {
+        let (count, target) = match text.split_once(' ') {
+            Some((count, target)) => (count, target.trim()),
+            None => (text, ""),
+        };
+        let count = count
+            .parse::<CardCount>()
+            .map_err(|_| format!("'{}' is not a number of copies to keep", count))?;
+        let target = if target.is_empty() {
+            Target::Everything
+        } else if let Some(t) = target.strip_prefix("type:") {
+            // No type has " for " in it, so that part has to be the purpose
+            if t.to_ascii_lowercase().contains(" for ") {
+                return Err(format!("{} is not a type", t.trim()));
+            }
+            Target::Type(t.trim().to_string())
+        } else {
+            match db.get_card(&target.to_string()) {
+                Some(card) => Target::Card(card.name.clone()),
+                None => return Err(format!("{} could not be found", target)),
+            }
+        };
+        Ok((count, target))
+    }
+
+    impl fmt::Display for KeepRule {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match &self.target {
+                Target::Everything => write!(f, "keep {} of every card", self.count)?,
+                Target::Type(t) => write!(f, "keep {} of every {}", self.count, t)?,
+                Target::Card(name) => write!(f, "keep {} {}", self.count, name)?,
+            }
+            if let Some(p) = &self.purpose {
+                write!(f, " for {}", p)?;
+            }
+            Ok(())
+        }
+    }
+
+    // Everything a user owns along with their keep rules. Whatever is above the rules is
+    // surplus, which can be linked to one of the user's lists as its haves.
+    #[derive(Clone, Default, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct Collection {
+        cards: CardList::CardList,
+        rules: Vec<KeepRule>,
+        linked: Option<String>,
+    }
+
+    pub fn new() -> Collection {
+        Collection::default()
+    }
+
+    impl Collection {
+        pub fn cards(&self) -> &CardList::CardList {
+            &self.cards
+        }
+
+        pub fn add(&mut self, entry: CardEntry::CardEntry) {
+            self.cards.add(entry);
+        }
+
+        // Returns the number of copies that were actually removed.
+        pub fn remove(&mut self, entry: &CardEntry::CardEntry) -> CardCount {
+            self.cards.remove(entry)
+        }
+
+        pub fn rules(&self) -> &[KeepRule] {
+            &self.rules
+        }
+
+        // Adds the rule, replacing any rule with the same target and purpose.
+        pub fn add_rule(&mut self, rule: KeepRule) {
+            match self.rules.iter_mut().find(|r| r.same_scope(&rule)) {
+                Some(r) => *r = rule,
+                None => self.rules.push(rule),
+            }
+        }
+
+        // Removes a rule by its number, as shown to users (starting at 1).
+        pub fn remove_rule(&mut self, number: usize) -> Result<KeepRule, String> {
+            if number == 0 || number > self.rules.len() {
+                return Err(format!("There is no rule number {}.", number));
+            }
+            Ok(self.rules.remove(number - 1))
+        }
+
+        // The list that the surplus is kept in, if any.
+        pub fn linked(&self) -> Option<&String> {
+            self.linked.as_ref()
+        }
+
+        pub fn set_linked(&mut self, list: Option<String>) {
+            self.linked = list;
+        }
+
+        // How many copies of the card the rules keep out of trades.
+        pub fn keep_count(&self, card: &Card::AtomicCard) -> CardCount {
+            let mut best: Vec<&KeepRule> = Vec::new();
+            for rule in self.rules.iter().filter(|r| r.applies(card)) {
+                match best.iter_mut().find(|b| b.same_purpose(rule)) {
+                    Some(b) if rule.specificity() > b.specificity() => *b = rule,
+                    Some(_) => {}
+                    None => best.push(rule),
+                }
+            }
+            best.iter().map(|r| r.count).sum()
+        }
+
+        // Every copy above the keep rules. The best copies are the ones that are kept, so
+        // played copies are traded before near mint ones, and collectibles (graded, signed,
+        // ...) are only traded once every ordinary copy is.
+        pub fn surplus(&self) -> CardList::CardList {
+            let mut digest = CardList::new();
+            let mut names: Vec<String> = self.cards.iter().map(|c| c.card.get_name()).collect();
+            names.sort();
+            names.dedup();
+            for name in names {
+                let mut copies: Vec<&CardEntry::CardEntry> = self.cards.get(&name).collect();
+                copies.sort_by_key(|c| (c.collectible().is_some(), c.get_condition()));
+                let mut keep = self.keep_count(&copies[0].card.card);
+                for copy in copies.into_iter().rev() {
+                    let kept = std::cmp::min(keep, copy.count);
+                    keep -= kept;
+                    if copy.count > kept {
+                        let mut spare = copy.clone();
+                        spare.update_count(copy.count - kept);
+                        digest.add(spare);
+                    }
+                }
+            }
+            digest
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::card_entry::CardEntry::Condition;
+
+        fn rule(db: &CardDB::CardDB, text: &str) -> KeepRule {
+            parse_rule(db, text).unwrap()
+        }
+
+        fn copies(db: &CardDB::CardDB, name: &str, count: CardCount, condition: Condition) -> CardEntry::CardEntry {
+            let card = Card::new(db.get_card(&name.to_string()).unwrap().clone(), String::new());
+            let mut digest = CardEntry::new(count, card);
+            digest.condition = Some(condition);
+            digest
+        }
+
+        #[test]
+        fn rules_are_read_with_targets_and_purposes() {
+            let db = CardDB::sample();
+            assert_eq!(rule(&db, "4"), KeepRule { count: 4, target: Target::Everything, purpose: None });
+            assert_eq!(rule(&db, "1 for Commander").purpose.as_deref(), Some("Commander"));
+            assert_eq!(rule(&db, "0 type:Land for Cube").target, Target::Type(String::from("Land")));
+            assert_eq!(rule(&db, "0 type:Land for Cube").purpose.as_deref(), Some("Cube"));
+            assert!(parse_rule(&db, "four").is_err());
+            assert!(parse_rule(&db, "2 Lightning Blot").is_err());
+        }
+
+        #[test]
+        fn card_names_can_contain_for() {
+            let db = CardDB::sample();
+            let quest = Target::Card(String::from("Quest for the Holy Relic"));
+            assert_eq!(rule(&db, "4 Quest for the Holy Relic"), KeepRule { count: 4, target: quest.clone(), purpose: None });
+            assert_eq!(rule(&db, "1 Quest for the Holy Relic for Cube").target, quest);
+            assert_eq!(rule(&db, "1 Quest for the Holy Relic for Cube").purpose.as_deref(), Some("Cube"));
+        }
+
+        #[test]
+        fn the_most_specific_rule_wins_within_a_purpose() {
+            let db = CardDB::sample();
+            let mut collection = new();
+            collection.add_rule(rule(&db, "4"));
+            collection.add_rule(rule(&db, "1 type:Instant"));
+            collection.add_rule(rule(&db, "8 Lightning Bolt"));
+            collection.add_rule(rule(&db, "1 for Commander"));
+            collection.add_rule(rule(&db, "2 for commander"));
+            assert_eq!(collection.rules().len(), 4);
+            let keep = |name: &str| collection.keep_count(db.get_card(&name.to_string()).unwrap());
+            assert_eq!(keep("Lightning Bolt"), 10);
+            assert_eq!(keep("Counterspell"), 3);
+            assert_eq!(keep("Duress"), 6);
+        }
+
+        #[test]
+        fn purposes_ignore_case() {
+            let db = CardDB::sample();
+            let mut collection = new();
+            collection.add_rule(rule(&db, "1 for Commander"));
+            collection.add_rule(rule(&db, "3 Duress for COMMANDER"));
+            assert_eq!(collection.keep_count(db.get_card(&String::from("Duress")).unwrap()), 3);
+        }
+
+        #[test]
+        fn the_worst_copies_are_surplus() {
+            let db = CardDB::sample();
+            let mut collection = new();
+            collection.add(copies(&db, "Lightning Bolt", 2, Condition::NearMint));
+            collection.add(copies(&db, "Lightning Bolt", 2, Condition::HeavilyPlayed));
+            collection.add(copies(&db, "Lightning Bolt", 1, Condition::LightlyPlayed));
+            collection.add(copies(&db, "Duress", 1, Condition::NearMint));
+            collection.add_rule(rule(&db, "3 Lightning Bolt"));
+            collection.add_rule(rule(&db, "1"));
+            let surplus = collection.surplus();
+            let spare: Vec<(CardCount, Condition)> = surplus.iter().map(|c| (c.count, c.get_condition())).collect();
+            assert_eq!(spare, vec![(2, Condition::HeavilyPlayed)]);
+        }
+    }
+}
@@ -0,0 +1,318 @@
+pub mod CollectionCsv {
+
+    use std::str::FromStr;
+
+    use crate::card::Card;
+    use crate::card_db::CardDB;
+    use crate::card_entry::CardEntry;
+    use crate::decklist::Decklist::LineError;
+    use crate::utils::Types::*;
+
+    // The collection managers whose CSV exports can be imported.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Format {
+        Deckbox,
+        Moxfield,
+        TCGplayer,
+        ManaBox,
+    }
+
+    impl FromStr for Format {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.to_lowercase().as_str() {
+                "deckbox" => Ok(Format::Deckbox),
+                "moxfield" => Ok(Format::Moxfield),
+                "tcgplayer" | "tcg" => Ok(Format::TCGplayer),
+                "manabox" => Ok(Format::ManaBox),
+                _ => Err(()),
+            }
+        }
+    }
+
+    // The headers that each piece of information is found under. Where more than one
+    // header is listed, the first one present in the file is used.
+    struct Columns {
+        count: &'static [&'static str],
+        name: &'static [&'static str],
+        set_code: &'static [&'static str],
+        set_name: &'static [&'static str],
+        condition: &'static [&'static str],
+        language: &'static [&'static str],
+        finish: &'static [&'static str],
+        signed: &'static [&'static str],
+        altered: &'static [&'static str],
+        misprint: &'static [&'static str],
+    }
+
+    impl Format {
+        fn columns(&self) -> Columns {
+            match self {
+                Format::Deckbox => Columns {
+                    count: &["Count"],
+                    name: &["Name"],
+                    set_code: &[],
+                    set_name: &["Edition"],
+                    condition: &["Condition"],
+                    language: &["Language"],
+                    finish: &["Foil"],
+                    signed: &["Signed"],
+                    altered: &["Altered Art"],
+                    misprint: &["Misprint"],
+                },
+                Format::Moxfield => Columns {
+                    count: &["Count"],
+                    name: &["Name"],
+                    set_code: &["Edition"],
+                    set_name: &[],
+                    condition: &["Condition"],
+                    language: &["Language"],
+                    finish: &["Foil"],
+                    signed: &[],
+                    altered: &["Alter"],
+                    misprint: &[],
+                },
+                Format::TCGplayer => Columns {
+                    count: &["Quantity"],
+                    name: &["Simple Name", "Name"],
+                    set_code: &["Set Code"],
+                    set_name: &["Set"],
+                    condition: &["Condition"],
+                    language: &["Language"],
+                    finish: &["Printing"],
+                    signed: &[],
+                    altered: &[],
+                    misprint: &[],
+                },
+                Format::ManaBox => Columns {
+                    count: &["Quantity"],
+                    name: &["Name"],
+                    set_code: &["Set code"],
+                    set_name: &["Set name"],
+                    condition: &["Condition"],
+                    language: &["Language"],
+                    finish: &["Foil"],
+                    signed: &[],
+                    altered: &["Altered"],
+                    misprint: &["Misprint"],
+                },
+            }
+        }
+    }
+
+    // Finds the index of the first of the given headers that is in the file.
+    fn find(headers: &csv::StringRecord, names: &[&str]) -> Option<usize> {
+        names
+            .iter()
+            .find_map(|n| headers.iter().position(|h| h.trim().eq_ignore_ascii_case(n)))
+    }
+
+    fn is_set(value: &str) -> bool {
+        !matches!(value.trim().to_lowercase().as_str(), "" | "false" | "0" | "no")
+    }
+
+    // A yes/no column that says yes.
+    fn is_flag(value: &str) -> bool {
+        matches!(value.trim().to_lowercase().as_str(), "true" | "yes" | "y" | "1" | "x")
+    }
+
+    // Parses a CSV export into card entries. Rows that can't be read are reported with the
+    // line they came from. Values that aren't understood, like an unknown condition, are
+    // left unspecified rather than rejecting the whole row.
+    pub fn parse(db: &CardDB::CardDB, format: Format, text: &str) -> (Vec<CardEntry::CardEntry>, Vec<LineError>) {
+        let mut digest = Vec::new();
+        let mut errors = Vec::new();
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(text.trim().as_bytes());
+        let headers = match reader.headers() {
+            Ok(h) => h.clone(),
+            Err(why) => {
+                errors.push(LineError { line: 1, reason: format!("The headers could not be read: {}", why) });
+                return (digest, errors);
+            }
+        };
+        let columns = format.columns();
+        let count_col = find(&headers, columns.count);
+        let name_col = match find(&headers, columns.name) {
+            Some(c) => c,
+            None => {
+                errors.push(LineError {
+                    line: 1,
+                    reason: format!("There is no '{}' column. Is this a {:?} export?", columns.name[0], format),
+                });
+                return (digest, errors);
+            }
+        };
+        let set_code_col = find(&headers, columns.set_code);
+        let set_name_col = find(&headers, columns.set_name);
+        let condition_col = find(&headers, columns.condition);
+        let language_col = find(&headers, columns.language);
+        let finish_col = find(&headers, columns.finish);
+        let signed_col = find(&headers, columns.signed);
+        let altered_col = find(&headers, columns.altered);
+        let misprint_col = find(&headers, columns.misprint);
+
+        for record in reader.records() {
+            let record = match record {
+                Ok(r) => r,
+                Err(why) => {
+                    let line = why.position().map_or(0, |p| p.line() as usize);
+                    errors.push(LineError { line, reason: why.to_string() });
+                    continue;
+                }
+            };
+            let line = record.position().map_or(0, |p| p.line() as usize);
+            let get = |col: Option<usize>| col.and_then(|c| record.get(c)).unwrap_or("").trim();
+
+            let count = match get(count_col) {
+                "" => 1,
+                c => match c.parse::<CardCount>() {
+                    Ok(c) => c,
+                    Err(_) => {
+                        errors.push(LineError { line, reason: format!("'{}' is not a quantity", c) });
+                        continue;
+                    }
+                },
+            };
+            let name = get(Some(name_col)).to_string();
+            let atomic = match db.get_card(&name) {
+                Some(a) => a,
+                None => {
+                    errors.push(LineError { line, reason: format!("'{}' could not be found", name) });
+                    continue;
+                }
+            };
+
+            // Prefer the set code, but fall back on the set name if the code isn't a printing
+            // of the card (e.g. promos that some tools give their own codes).
+            let mut card = Card::new(atomic.clone(), get(set_code_col).to_string());
+            if !card.is_valid_printing() || card.pattern.is_some() || card.printing.is_empty() {
+                let code = db.set_code(get(set_name_col)).cloned().unwrap_or_default();
+                card = Card::new(atomic.clone(), code);
+                if !card.is_valid_printing() {
+                    card = Card::new(atomic.clone(), String::new());
+                }
+            }
+
+            let mut entry = CardEntry::new(count, card);
+            entry.condition = get(condition_col).parse::<CardEntry::Condition>().ok();
+            entry.language = get(language_col).parse::<CardEntry::Language>().ok();
+            // Some tools only mark foils, e.g. with "true" or "foil", and leave the rest blank.
+            // Any other value is left unspecified rather than guessed at.
+            let finish = get(finish_col);
+            entry.finish = match finish.parse::<CardEntry::Finish>() {
+                Ok(f) => Some(f),
+                Err(_) if is_flag(finish) => Some(CardEntry::Finish::Foil),
+                Err(_) => None,
+            };
+            if is_set(get(signed_col)) {
+                entry.set_attribute("signed");
+            }
+            if is_set(get(altered_col)) {
+                entry.set_attribute("altered");
+            }
+            if is_set(get(misprint_col)) {
+                entry.set_attribute("misprint");
+            }
+            digest.push(entry);
+        }
+        (digest, errors)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::card_entry::CardEntry::{Condition, Finish, Language};
+
+        fn read(format: Format, text: &str) -> Vec<CardEntry::CardEntry> {
+            let (digest, errors) = parse(&CardDB::sample(), format, text);
+            assert!(errors.is_empty(), "{}", errors.iter().map(|e| e.to_string()).collect::<Vec<String>>().join("\n"));
+            digest
+        }
+
+        #[test]
+        fn deckbox_exports() {
+            let text = "Count,Tradelist Count,Name,Edition,Card Number,Condition,Language,Foil,Signed,Artist Proof,Altered Art,Misprint
+2,0,Lightning Bolt,Magic 2011,146,Near Mint,English,foil,,,,
+1,0,Counterspell,Limited Edition Alpha,,Good (Lightly Played),Japanese,,signed,,altered,";
+            let cards = read(Format::Deckbox, text);
+            assert_eq!(cards.len(), 2);
+            assert_eq!((cards[0].count, cards[0].card.printing.as_str()), (2, "M11"));
+            assert_eq!(cards[0].finish, Some(Finish::Foil));
+            assert_eq!(cards[0].condition, Some(Condition::NearMint));
+            assert_eq!(cards[1].card.printing, "LEA");
+            assert_eq!(cards[1].finish, None);
+            assert_eq!(cards[1].condition, Some(Condition::LightlyPlayed));
+            assert_eq!(cards[1].language, Some(Language::Japanese));
+            let collectible = cards[1].collectible.as_ref().unwrap();
+            assert!(collectible.signed && collectible.altered && !collectible.misprint);
+        }
+
+        #[test]
+        fn moxfield_exports() {
+            let text = "Count,Tradelist Count,Name,Edition,Condition,Language,Foil,Tags,Last Modified,Collector Number,Alter,Proxy
+4,4,Lightning Bolt,m10,NM,English,etched,,,,False,False
+1,1,Duress,usg,LP,German,,,,,True,False";
+            let cards = read(Format::Moxfield, text);
+            assert_eq!(cards[0].card.printing, "M10");
+            assert_eq!(cards[0].finish, Some(Finish::Etched));
+            assert!(cards[0].collectible.is_none());
+            assert_eq!(cards[1].card.printing, "USG");
+            assert_eq!(cards[1].language, Some(Language::German));
+            assert!(cards[1].collectible.as_ref().unwrap().altered);
+        }
+
+        #[test]
+        fn tcgplayer_exports() {
+            let text = "Quantity,Name,Simple Name,Set,Card Number,Set Code,Printing,Condition,Language,Rarity
+3,Lightning Bolt (Retro Frame),Lightning Bolt,Magic 2010,,PRM,Foil,Moderately Played,English,Common
+1,Forest,Forest,Magic 2011,,M11,Normal,Near Mint,English,Land";
+            let cards = read(Format::TCGplayer, text);
+            // The set code isn't a printing of the card, so the set name is used instead
+            assert_eq!(cards[0].card.printing, "M10");
+            assert_eq!(cards[0].finish, Some(Finish::Foil));
+            assert_eq!(cards[0].condition, Some(Condition::ModeratelyPlayed));
+            assert_eq!(cards[1].card.printing, "M11");
+            assert_eq!(cards[1].finish, Some(Finish::Nonfoil));
+        }
+
+        #[test]
+        fn manabox_exports() {
+            let text = "Name,Set code,Set name,Collector number,Foil,Rarity,Quantity,ManaBox ID,Scryfall ID,Purchase price,Misprint,Altered,Condition,Language,Purchase price currency
+Lightning Bolt,M11,Magic 2011,146,normal,common,2,1,abc,0.5,false,false,near_mint,en,USD
+Counterspell,LEA,Limited Edition Alpha,54,foil,uncommon,1,2,def,900,true,false,heavily_played,ja,USD";
+            let cards = read(Format::ManaBox, text);
+            assert_eq!((cards[0].count, cards[0].card.printing.as_str()), (2, "M11"));
+            assert_eq!(cards[0].finish, Some(Finish::Nonfoil));
+            assert!(cards[0].collectible.is_none());
+            assert_eq!(cards[1].finish, Some(Finish::Foil));
+            assert_eq!(cards[1].condition, Some(Condition::HeavilyPlayed));
+            assert_eq!(cards[1].language, Some(Language::Japanese));
+            assert!(cards[1].collectible.as_ref().unwrap().misprint);
+        }
+
+        #[test]
+        fn foil_flags_and_unknown_values() {
+            let text = "Count,Name,Foil
+1,Lightning Bolt,true
+1,Duress,Yes
+1,Counterspell,Prerelease
+1,Forest,false";
+            let finishes: Vec<Option<Finish>> = read(Format::Deckbox, text).iter().map(|c| c.finish).collect();
+            assert_eq!(finishes, vec![Some(Finish::Foil), Some(Finish::Foil), None, None]);
+        }
+
+        #[test]
+        fn bad_rows_are_reported() {
+            let text = "Count,Name\n2,Lightning Bolt\nmany,Duress\n1,Lightning Blot";
+            let (cards, errors) = parse(&CardDB::sample(), Format::Moxfield, text);
+            assert_eq!(cards.len(), 1);
+            let lines: Vec<usize> = errors.iter().map(|e| e.line).collect();
+            assert_eq!(lines, vec![3, 4]);
+            let (_, errors) = parse(&CardDB::sample(), Format::TCGplayer, "Count,Card\n1,Duress");
+            assert!(errors[0].reason.contains("TCGplayer"));
+        }
+    }
+}
@@ -0,0 +1,320 @@
+pub mod Decklist {
+
+    use std::fmt;
+
+    use crate::card::Card;
+    use crate::card::Card::PrintingPattern;
+    use crate::card_db::CardDB;
+    use crate::card_entry::CardEntry;
+    use crate::utils::Types::*;
+
+    // Reads decklists as they are exported from the popular clients. Every line is one of:
+    //  - an entry: "4 Lightning Bolt", "4x Lightning Bolt", "Lightning Bolt x4",
+    //    "4 Lightning Bolt (M11) 146" (MTG Arena), or "4 Lightning Bolt [M11] foil LP"
+    //  - a section header: "Deck", "Sideboard", "Commander", "SB: 4 Duress", ...
+    //  - a comment: anything starting with "//" or "#"
+    //  - a blank line, which separates the main deck from the sideboard in MTGO lists
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Section {
+        Deck,
+        Sideboard,
+        Commander,
+        Companion,
+        Maybeboard,
+    }
+
+    pub struct LineError {
+        pub line: usize,
+        pub reason: String,
+    }
+
+    impl fmt::Display for LineError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Line {}: {}", self.line, self.reason)
+        }
+    }
+
+    pub struct Decklist {
+        pub entries: Vec<(Section, CardEntry::CardEntry)>,
+        pub errors: Vec<LineError>,
+    }
+
+    pub fn parse(db: &CardDB::CardDB, text: &str) -> Decklist {
+        let mut digest = Decklist { entries: Vec::new(), errors: Vec::new() };
+        let mut section = Section::Deck;
+        let mut saw_header = false;
+        for (i, raw) in text.lines().enumerate() {
+            let mut line = raw.trim();
+            if line.is_empty() {
+                // MTGO lists have no headers, just a blank line before the sideboard
+                if !saw_header && !digest.entries.is_empty() {
+                    section = Section::Sideboard;
+                }
+                continue;
+            }
+            if let Some(s) = parse_header(line) {
+                section = s;
+                saw_header = true;
+                continue;
+            }
+            if line.starts_with("//") || line.starts_with('#') {
+                continue;
+            }
+            let mut line_section = section;
+            if let Some(rest) = line.strip_prefix("SB:") {
+                line_section = Section::Sideboard;
+                line = rest.trim();
+            }
+            match parse_line(db, line) {
+                Ok(entry) => digest.entries.push((line_section, entry)),
+                Err(reason) => digest.errors.push(LineError { line: i + 1, reason }),
+            }
+        }
+        digest
+    }
+
+    fn parse_header(line: &str) -> Option<Section> {
+        let header = line
+            .trim_start_matches("//")
+            .trim_end_matches(':')
+            .trim()
+            .to_lowercase();
+        match header.as_str() {
+            "deck" | "main" | "mainboard" | "main deck" => Some(Section::Deck),
+            "sideboard" | "side" => Some(Section::Sideboard),
+            "commander" | "commanders" => Some(Section::Commander),
+            "companion" => Some(Section::Companion),
+            "maybeboard" | "maybe" => Some(Section::Maybeboard),
+            _ => None,
+        }
+    }
+
+    // Reads "4", "4x", or "x4" as a quantity.
+    fn parse_quantity(token: &str) -> Option<CardCount> {
+        let token = token.to_lowercase();
+        let digits = token.trim_end_matches('x').trim_start_matches('x');
+        if digits.len() == token.len() || digits.len() + 1 == token.len() {
+            digits.parse::<CardCount>().ok()
+        } else {
+            None
+        }
+    }
+
+    // Parses a single entry line. Lines without a quantity are read as a single copy.
+    pub fn parse_line(db: &CardDB::CardDB, line: &str) -> Result<CardEntry::CardEntry, String> {
+        let mut tokens: Vec<&str> = line.split_whitespace().collect();
+        let mut count: CardCount = 1;
+        if let Some(c) = tokens.first().and_then(|t| parse_quantity(t)) {
+            count = c;
+            tokens.remove(0);
+        } else if let Some(c) = tokens.last().filter(|t| t.to_lowercase().starts_with('x')).and_then(|t| parse_quantity(t)) {
+            count = c;
+            tokens.pop();
+        }
+        if tokens.is_empty() {
+            return Err(format!("'{}' does not name a card", line));
+        }
+        match parse_arena(db, &tokens, count) {
+            Some(entry) => Ok(entry),
+            None => parse_card_details(db, &tokens.join(" "), count),
+        }
+    }
+
+    // Reads the MTG Arena form, "Lightning Bolt (M11) 146", where the collector number is
+    // optional and may be followed by a "*F*" or "*E*" finish marker. Arena sometimes uses
+    // its own set codes, so a set that isn't a known printing of the card is dropped
+    // rather than rejecting the line.
+    fn parse_arena(db: &CardDB::CardDB, tokens: &[&str], count: CardCount) -> Option<CardEntry::CardEntry> {
+        let open = tokens.iter().rposition(|t| t.starts_with('(') && t.ends_with(')'))?;
+        let rest = &tokens[open + 1..];
+        if rest.len() > 2 {
+            return None;
+        }
+        let name = tokens[..open].join(" ");
+        let atomic = db.get_card(&name)?;
+        let set = tokens[open].trim_start_matches('(').trim_end_matches(')');
+        let mut card = Card::new(atomic.clone(), set.to_string());
+        if !card.is_valid_printing() || card.pattern.is_some() {
+            card = Card::new(atomic.clone(), String::new());
+        }
+        let mut digest = CardEntry::new(count, card);
+        for marker in rest {
+            match *marker {
+                "*F*" => digest.set_attribute("foil"),
+                "*E*" => digest.set_attribute("etched"),
+                _ => true,
+            };
+        }
+        Some(digest)
+    }
+
+    // Splits "Card Name [SET]" into the card name and printing. The printing is optional.
+    pub fn split_printing(text: &str) -> (String, String) {
+        if let (Some(open), true) = (text.rfind('['), text.ends_with(']')) {
+            (text[..open].trim().to_string(), text[open + 1..text.len() - 1].trim().to_string())
+        } else {
+            (text.trim().to_string(), String::new())
+        }
+    }
+
+    // Parses "Card Name [SET] details" where the details are any mix of finish, condition,
+    // language, and collectible attributes (e.g. "PSA 9 signed") and a note in quotes.
+    pub fn parse_card_details(db: &CardDB::CardDB, text: &str, count: CardCount) -> Result<CardEntry::CardEntry, String> {
+        let mut text = text.trim();
+        let mut note: Option<String> = None;
+        if let Some(quoted) = text.strip_suffix('"') {
+            if let Some(open) = quoted.rfind('"') {
+                note = Some(quoted[open + 1..].to_string());
+                text = quoted[..open].trim();
+            }
+        }
+        let mut tokens: Vec<&str> = text.split_whitespace().collect();
+        let mut attributes: Vec<String> = Vec::new();
+        // Trailing tokens are attributes unless they are part of the card's name.
+        while tokens.len() > 1 && db.get_card(&split_printing(&tokens.join(" ")).0).is_none() {
+            let last = tokens[tokens.len() - 1];
+            let grade = format!("{} {}", tokens[tokens.len() - 2], last);
+            if tokens.len() > 2 && CardEntry::is_attribute(&grade) {
+                attributes.push(grade);
+                tokens.truncate(tokens.len() - 2);
+            } else if CardEntry::is_attribute(last) {
+                attributes.push(last.to_string());
+                tokens.pop();
+            } else {
+                break;
+            }
+        }
+        let (entry_name, entry_printing) = split_printing(&tokens.join(" "));
+        let entry_card = db
+            .get_card(&entry_name)
+            .ok_or_else(|| format!("'{}' could not be found", entry_name))?;
+        let card = Card::new(entry_card.clone(), entry_printing);
+        if !card.is_valid_printing() {
+            return Err(format!("'{}' names a set that {} was never printed in", card, entry_name));
+        }
+        match card.pattern {
+            Some(PrintingPattern::Cheapest) if !db.has_prices() => {
+                return Err(format!("'{}' can't be used, since there is no price list to find the cheapest printing with", card));
+            }
+            Some(PrintingPattern::Oldest) if !db.has_release_dates() => {
+                return Err(format!("'{}' can't be used, since there is no set list to find the oldest printing with", card));
+            }
+            _ => {}
+        }
+        let mut digest = CardEntry::new(count, card);
+        for a in attributes {
+            digest.set_attribute(&a);
+        }
+        if let Some(n) = note {
+            digest.set_note(n);
+        }
+        Ok(digest)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::card_entry::CardEntry::{Condition, Finish};
+
+        fn summary(list: &Decklist) -> Vec<(Section, CardCount, String, String)> {
+            list.entries
+                .iter()
+                .map(|(section, e)| (*section, e.count, e.card.get_name(), e.card.printing.clone()))
+                .collect()
+        }
+
+        fn line(section: Section, count: CardCount, name: &str, printing: &str) -> (Section, CardCount, String, String) {
+            (section, count, name.to_string(), printing.to_string())
+        }
+
+        #[test]
+        fn quantities_are_read_in_every_form() {
+            let list = parse(&CardDB::sample(), "4 Lightning Bolt\n3x Duress\nCounterspell x2\nForest");
+            assert!(list.errors.is_empty());
+            assert_eq!(
+                summary(&list),
+                vec![
+                    line(Section::Deck, 4, "Lightning Bolt", ""),
+                    line(Section::Deck, 3, "Duress", ""),
+                    line(Section::Deck, 2, "Counterspell", ""),
+                    line(Section::Deck, 1, "Forest", ""),
+                ]
+            );
+        }
+
+        #[test]
+        fn arena_lists_keep_sets_and_sections() {
+            let text = "Deck\n4 Lightning Bolt (M11) 146\n1 Forest (XYZ) 12\n\nSideboard\n2 Duress (M19) 94 *F*";
+            let list = parse(&CardDB::sample(), text);
+            assert!(list.errors.is_empty());
+            assert_eq!(
+                summary(&list),
+                vec![
+                    line(Section::Deck, 4, "Lightning Bolt", "M11"),
+                    // Arena's own set codes are dropped rather than rejected
+                    line(Section::Deck, 1, "Forest", ""),
+                    line(Section::Sideboard, 2, "Duress", "M19"),
+                ]
+            );
+            assert_eq!(list.entries[2].1.finish, Some(Finish::Foil));
+        }
+
+        #[test]
+        fn mtgo_lists_start_the_sideboard_at_a_blank_line() {
+            let list = parse(&CardDB::sample(), "\n4 Lightning Bolt\n4 Counterspell\n\n2 Duress");
+            assert_eq!(
+                summary(&list),
+                vec![
+                    line(Section::Deck, 4, "Lightning Bolt", ""),
+                    line(Section::Deck, 4, "Counterspell", ""),
+                    line(Section::Sideboard, 2, "Duress", ""),
+                ]
+            );
+        }
+
+        #[test]
+        fn headers_comments_and_sideboard_prefixes() {
+            let text = "// Commander\n1 Forest\n\n# my notes\nMain:\n4 Lightning Bolt\n\nSB: 2 Duress\n// Sideboard\n1 Counterspell";
+            let list = parse(&CardDB::sample(), text);
+            assert!(list.errors.is_empty());
+            assert_eq!(
+                summary(&list),
+                vec![
+                    line(Section::Commander, 1, "Forest", ""),
+                    line(Section::Deck, 4, "Lightning Bolt", ""),
+                    line(Section::Sideboard, 2, "Duress", ""),
+                    line(Section::Sideboard, 1, "Counterspell", ""),
+                ]
+            );
+        }
+
+        #[test]
+        fn bad_lines_are_reported_with_their_number() {
+            let list = parse(&CardDB::sample(), "4 Lightning Bolt\n2 Lightning Blot\n1 Duress [M11]\nx4");
+            assert_eq!(list.entries.len(), 1);
+            let lines: Vec<usize> = list.errors.iter().map(|e| e.line).collect();
+            assert_eq!(lines, vec![2, 3, 4]);
+            assert_eq!(list.errors[0].to_string(), "Line 2: 'Lightning Blot' could not be found");
+        }
+
+        #[test]
+        fn details_follow_the_card_name() {
+            let entry = parse_line(&CardDB::sample(), "2 Lightning Bolt [m11] foil LP \"from my first deck\"").unwrap();
+            assert_eq!(entry.count, 2);
+            assert_eq!(entry.card.printing, "M11");
+            assert_eq!(entry.finish, Some(Finish::Foil));
+            assert_eq!(entry.condition, Some(Condition::LightlyPlayed));
+            assert_eq!(entry.collectible.unwrap().note.as_deref(), Some("from my first deck"));
+            let quest = parse_line(&CardDB::sample(), "1 Quest for the Holy Relic").unwrap();
+            assert_eq!(quest.card.get_name(), "Quest for the Holy Relic");
+        }
+
+        #[test]
+        fn preferences_need_their_data() {
+            let db = CardDB::sample();
+            assert!(parse_line(&db, "1 Lightning Bolt [oldest]").is_ok());
+            // The sample has release dates but no prices
+            assert!(parse_line(&db, "1 Lightning Bolt [cheapest]").err().unwrap().contains("no price list"));
+        }
+    }
+}
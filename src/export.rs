@@ -0,0 +1,200 @@
+pub mod Export {
+
+    use std::str::FromStr;
+
+    use serde::Serialize;
+
+    use crate::card_db::CardDB;
+    use crate::card_entry::CardEntry;
+    use crate::card_list::CardList;
+    use crate::utils::Types::*;
+
+    // The formats that a list of cards can be exported to. Text can be read back in by
+    // '!tradelist add', Arena by MTG Arena, and the CSVs by their collection managers.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Format {
+        Text,
+        Arena,
+        Deckbox,
+        Moxfield,
+        Json,
+    }
+
+    impl FromStr for Format {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.to_lowercase().as_str() {
+                "text" | "txt" => Ok(Format::Text),
+                "arena" | "mtga" => Ok(Format::Arena),
+                "deckbox" => Ok(Format::Deckbox),
+                "moxfield" => Ok(Format::Moxfield),
+                "json" => Ok(Format::Json),
+                _ => Err(()),
+            }
+        }
+    }
+
+    impl Format {
+        fn extension(&self) -> &'static str {
+            match self {
+                Format::Text | Format::Arena => "txt",
+                Format::Deckbox | Format::Moxfield => "csv",
+                Format::Json => "json",
+            }
+        }
+    }
+
+    // A flat description of an entry for the json export. This is kept separate from
+    // CardEntry so the exported format doesn't change with how entries are stored.
+    #[derive(Serialize)]
+    struct ExportEntry {
+        count: CardCount,
+        name: String,
+        set: Option<String>,
+        condition: Option<String>,
+        finish: Option<String>,
+        language: Option<String>,
+        grade: Option<String>,
+        signed: bool,
+        altered: bool,
+        misprint: bool,
+        note: Option<String>,
+    }
+
+    impl ExportEntry {
+        fn new(entry: &CardEntry::CardEntry) -> ExportEntry {
+            let collectible = entry.collectible.clone().unwrap_or_default();
+            ExportEntry {
+                count: entry.count,
+                name: entry.card.get_name(),
+                set: Some(entry.card.printing.clone()).filter(|p| !p.is_empty()),
+                condition: entry.condition.map(|c| c.to_string()),
+                finish: entry.finish.map(|f| f.to_string()),
+                language: entry.language.map(|l| l.to_string()),
+                grade: collectible.grade.map(|g| g.to_string()),
+                signed: collectible.signed,
+                altered: collectible.altered,
+                misprint: collectible.misprint,
+                note: collectible.note,
+            }
+        }
+    }
+
+    // Deckbox uses its own names for conditions.
+    fn deckbox_condition(condition: CardEntry::Condition) -> &'static str {
+        match condition {
+            CardEntry::Condition::NearMint => "Near Mint",
+            CardEntry::Condition::LightlyPlayed => "Good (Lightly Played)",
+            CardEntry::Condition::ModeratelyPlayed => "Played",
+            CardEntry::Condition::HeavilyPlayed => "Heavily Played",
+            CardEntry::Condition::Damaged => "Poor",
+        }
+    }
+
+    fn flag(value: bool, word: &str) -> String {
+        if value { word.to_string() } else { String::new() }
+    }
+
+    pub fn write_csv(headers: &[&str], rows: Vec<Vec<String>>) -> Result<Vec<u8>, String> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(headers).map_err(|e| e.to_string())?;
+        for row in rows {
+            writer.write_record(&row).map_err(|e| e.to_string())?;
+        }
+        writer.into_inner().map_err(|e| e.to_string())
+    }
+
+    // Exports the list, returning a filename and the file's contents.
+    pub fn export(db: &CardDB::CardDB, list: &CardList::CardList, format: Format, name: &str) -> Result<(String, Vec<u8>), String> {
+        let mut entries: Vec<&CardEntry::CardEntry> = list.iter().collect();
+        entries.sort_by_key(|e| (e.card.get_name(), e.card.printing.clone()));
+        let filename = format!("{}.{}", name, format.extension());
+
+        let data = match format {
+            Format::Text => entries
+                .iter()
+                .map(|e| format!("{}\n", e))
+                .collect::<String>()
+                .into_bytes(),
+            Format::Arena => {
+                let mut digest = String::from("Deck\n");
+                for e in entries {
+                    if e.card.printing.is_empty() {
+                        digest += &format!("{} {}\n", e.count, e.card.get_name());
+                    } else {
+                        digest += &format!("{} {} ({})\n", e.count, e.card.get_name(), e.card.printing);
+                    }
+                }
+                digest.into_bytes()
+            }
+            Format::Deckbox => {
+                let rows = entries
+                    .iter()
+                    .map(|e| {
+                        let collectible = e.collectible.clone().unwrap_or_default();
+                        vec![
+                            e.count.to_string(),
+                            e.count.to_string(),
+                            e.card.get_name(),
+                            db.set_name(&e.card.printing).cloned().unwrap_or_default(),
+                            String::new(),
+                            deckbox_condition(e.get_condition()).to_string(),
+                            e.get_language().name().to_string(),
+                            flag(e.get_finish() != CardEntry::Finish::Nonfoil, "foil"),
+                            flag(collectible.signed, "signed"),
+                            String::new(),
+                            flag(collectible.altered, "altered"),
+                            flag(collectible.misprint, "misprint"),
+                            String::new(),
+                            String::new(),
+                            String::new(),
+                        ]
+                    })
+                    .collect();
+                write_csv(
+                    &["Count", "Tradelist Count", "Name", "Edition", "Card Number", "Condition", "Language", "Foil",
+                      "Signed", "Artist Proof", "Altered Art", "Misprint", "Promo", "Textless", "My Price"],
+                    rows,
+                )?
+            }
+            Format::Moxfield => {
+                let rows = entries
+                    .iter()
+                    .map(|e| {
+                        let collectible = e.collectible.clone().unwrap_or_default();
+                        let foil = match e.get_finish() {
+                            CardEntry::Finish::Nonfoil => String::new(),
+                            f => f.to_string(),
+                        };
+                        vec![
+                            e.count.to_string(),
+                            e.count.to_string(),
+                            e.card.get_name(),
+                            e.card.printing.to_lowercase(),
+                            e.get_condition().name().to_string(),
+                            e.get_language().name().to_string(),
+                            foil,
+                            String::new(),
+                            String::new(),
+                            String::new(),
+                            if collectible.altered { String::from("True") } else { String::from("False") },
+                            String::from("False"),
+                            String::new(),
+                        ]
+                    })
+                    .collect();
+                write_csv(
+                    &["Count", "Tradelist Count", "Name", "Edition", "Condition", "Language", "Foil", "Tags",
+                      "Last Modified", "Collector Number", "Alter", "Proxy", "Purchase Price"],
+                    rows,
+                )?
+            }
+            Format::Json => {
+                let digest: Vec<ExportEntry> = entries.into_iter().map(ExportEntry::new).collect();
+                serde_json::to_vec_pretty(&digest).map_err(|e| e.to_string())?
+            }
+        };
+        Ok((filename, data))
+    }
+}
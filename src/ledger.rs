@@ -0,0 +1,109 @@
+pub mod Ledger {
+
+    use serenity::model::id::UserId;
+
+    use crate::card_entry::CardEntry;
+    use crate::export::Export;
+    use crate::proposal::Proposal;
+
+    // A completed trade from one user's side.
+    pub struct Record {
+        pub id: u64,
+        pub completed: u64,
+        pub partner: UserId,
+        pub gave: Vec<CardEntry::CardEntry>,
+        pub received: Vec<CardEntry::CardEntry>,
+        pub value_given: Option<f64>,
+        pub value_received: Option<f64>,
+        // What each side said their cards were worth
+        pub declared_given: Option<f64>,
+        pub declared_received: Option<f64>,
+    }
+
+    // Every trade that the user completed, newest first.
+    pub fn history(user: UserId, proposals: &[Proposal::Proposal]) -> Vec<Record> {
+        let mut digest: Vec<Record> = proposals
+            .iter()
+            .filter(|p| p.involves(user) && p.status() == Proposal::Status::Completed)
+            .map(|p| {
+                let completed = p.completed.unwrap_or(p.created);
+                if p.from == user {
+                    Record { id: p.id, completed, partner: p.to, gave: p.give.clone(), received: p.get.clone(), value_given: p.give_value, value_received: p.get_value, declared_given: p.give_declared, declared_received: p.get_declared }
+                } else {
+                    Record { id: p.id, completed, partner: p.from, gave: p.get.clone(), received: p.give.clone(), value_given: p.get_value, value_received: p.give_value, declared_given: p.get_declared, declared_received: p.give_declared }
+                }
+            })
+            .collect();
+        digest.sort_by(|a, b| b.completed.cmp(&a.completed).then(b.id.cmp(&a.id)));
+        digest
+    }
+
+    // Formats a Unix time as a date like 2021-10-31 (UTC).
+    pub fn date(time: u64) -> String {
+        // Howard Hinnant's days to civil date algorithm
+        let days = (time / 86_400) as i64 + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+
+    fn money(value: Option<f64>) -> String {
+        value.map(|v| format!("{:.2}", v)).unwrap_or_default()
+    }
+
+    fn cards(entries: &[CardEntry::CardEntry]) -> String {
+        entries.iter().map(|c| c.to_string()).collect::<Vec<String>>().join("; ")
+    }
+
+    impl Record {
+        // A line for the history embed. Discord shows the time in each reader's time zone.
+        pub fn line(&self) -> String {
+            let mut digest = format!(
+                "<t:{}:d> #{} with <@{}>: gave {}, received {}",
+                self.completed,
+                self.id,
+                self.partner,
+                Proposal::describe_cards(&self.gave),
+                Proposal::describe_cards(&self.received)
+            );
+            if self.value_given.is_some() || self.value_received.is_some() {
+                digest += &format!(" (priced ${} for ${})", money(self.value_given), money(self.value_received));
+            }
+            if self.declared_given.is_some() || self.declared_received.is_some() {
+                digest += &format!(" (declared ${} for ${})", money(self.declared_given), money(self.declared_received));
+            }
+            digest
+        }
+    }
+
+    // The history as a CSV file, for spreadsheets.
+    pub fn to_csv(user: UserId, records: &[Record]) -> Result<Vec<u8>, String> {
+        let rows = records
+            .iter()
+            .map(|r| {
+                vec![
+                    r.id.to_string(),
+                    date(r.completed),
+                    user.to_string(),
+                    r.partner.to_string(),
+                    cards(&r.gave),
+                    cards(&r.received),
+                    money(r.value_given),
+                    money(r.value_received),
+                    money(r.declared_given),
+                    money(r.declared_received),
+                ]
+            })
+            .collect();
+        Export::write_csv(
+            &["Trade", "Date", "User", "Partner", "Gave", "Received", "Value Given", "Value Received", "Declared Given", "Declared Received"],
+            rows,
+        )
+    }
+}
@@ -0,0 +1,372 @@
+pub mod ListView {
+
+    use std::cmp::Ordering;
+    use std::str::FromStr;
+
+    use crate::card_db::CardDB;
+    use crate::card_entry::CardEntry;
+    use crate::card_list::CardList;
+    use crate::pager::Pager;
+    use crate::utils::Types::*;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum SortKey {
+        Name,
+        Set,
+        Color,
+        Type,
+        ManaValue,
+        Count,
+        Value,
+    }
+
+    impl FromStr for SortKey {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.to_lowercase().as_str() {
+                "name" => Ok(SortKey::Name),
+                "set" | "edition" => Ok(SortKey::Set),
+                "color" | "colour" => Ok(SortKey::Color),
+                "type" => Ok(SortKey::Type),
+                "mv" | "cmc" | "manavalue" | "mana_value" => Ok(SortKey::ManaValue),
+                "count" | "qty" | "quantity" => Ok(SortKey::Count),
+                "value" | "price" => Ok(SortKey::Value),
+                _ => Err(()),
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum GroupKey {
+        Set,
+        Color,
+        Type,
+        Rarity,
+    }
+
+    impl FromStr for GroupKey {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.to_lowercase().as_str() {
+                "set" | "edition" => Ok(GroupKey::Set),
+                "color" | "colour" => Ok(GroupKey::Color),
+                "type" => Ok(GroupKey::Type),
+                "rarity" => Ok(GroupKey::Rarity),
+                _ => Err(()),
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum Comparison {
+        Equal,
+        Less,
+        LessOrEqual,
+        Greater,
+        GreaterOrEqual,
+    }
+
+    impl Comparison {
+        fn test<T: PartialOrd>(&self, value: T, target: T) -> bool {
+            match self {
+                Comparison::Equal => value == target,
+                Comparison::Less => value < target,
+                Comparison::LessOrEqual => value <= target,
+                Comparison::Greater => value > target,
+                Comparison::GreaterOrEqual => value >= target,
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Test {
+        Name(String),
+        Set(String),
+        // Color letters; with exact, the card must have exactly these colors
+        Colors(String, bool),
+        Type(String),
+        Rarity(String),
+        ManaValue(Comparison, f64),
+        Count(Comparison, CardCount),
+        Value(Comparison, f64),
+        Condition(Comparison, CardEntry::Condition),
+        Finish(CardEntry::Finish),
+        Language(CardEntry::Language),
+    }
+
+    // A filter like "t:creature", "mv<=2", "c:rg", "cond>=LP", "foil" or "-set:LEA". A
+    // leading "-" keeps only the entries that don't pass.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Filter {
+        negate: bool,
+        test: Test,
+    }
+
+    fn split_comparison(token: &str) -> Option<(&str, Comparison, &str)> {
+        for (op, comparison) in [
+            (">=", Comparison::GreaterOrEqual),
+            ("<=", Comparison::LessOrEqual),
+            (">", Comparison::Greater),
+            ("<", Comparison::Less),
+            ("=", Comparison::Equal),
+            (":", Comparison::Equal),
+        ] {
+            if let Some(i) = token.find(op) {
+                return Some((&token[..i], comparison, &token[i + op.len()..]));
+            }
+        }
+        None
+    }
+
+    impl FromStr for Filter {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let (negate, token) = match s.strip_prefix('-') {
+                Some(t) => (true, t),
+                None => (false, s),
+            };
+            if let Ok(finish) = token.parse::<CardEntry::Finish>() {
+                return Ok(Filter { negate, test: Test::Finish(finish) });
+            }
+            let (key, comparison, value) = split_comparison(token).ok_or_else(|| format!("'{}' is not a filter", s))?;
+            let exact = token[key.len()..].starts_with('=');
+            let number = || value.parse::<f64>().map_err(|_| format!("'{}' is not a number", value));
+            let test = match key.to_lowercase().as_str() {
+                "name" | "n" => Test::Name(value.to_lowercase()),
+                "set" | "s" | "e" => Test::Set(value.to_uppercase()),
+                "color" | "colour" | "c" => Test::Colors(value.to_uppercase(), exact),
+                "type" | "t" => Test::Type(value.to_lowercase()),
+                "rarity" | "r" => Test::Rarity(value.to_lowercase()),
+                "mv" | "cmc" => Test::ManaValue(comparison, number()?),
+                "count" | "qty" => Test::Count(comparison, number()? as CardCount),
+                "value" | "price" => Test::Value(comparison, number()?),
+                "condition" | "cond" => Test::Condition(
+                    comparison,
+                    value.parse().map_err(|_| format!("'{}' is not a condition", value))?,
+                ),
+                "finish" => Test::Finish(value.parse().map_err(|_| format!("'{}' is not a finish", value))?),
+                "language" | "lang" => Test::Language(value.parse().map_err(|_| format!("'{}' is not a language", value))?),
+                _ => return Err(format!("'{}' is not something that can be filtered on", key)),
+            };
+            Ok(Filter { negate, test })
+        }
+    }
+
+    fn colors(db: &CardDB::CardDB, entry: &CardEntry::CardEntry) -> Vec<String> {
+        db.details(&entry.card.get_name()).map(|d| d.colors.clone()).unwrap_or_default()
+    }
+
+    fn value(db: &CardDB::CardDB, entry: &CardEntry::CardEntry) -> Option<f64> {
+        db.price(&entry.card.get_name(), &entry.card.printing).map(|p| p * entry.count as f64)
+    }
+
+    impl Filter {
+        pub fn accepts(&self, db: &CardDB::CardDB, entry: &CardEntry::CardEntry) -> bool {
+            let name = entry.card.get_name();
+            let passes = match &self.test {
+                Test::Name(n) => name.to_lowercase().contains(n.as_str()),
+                Test::Set(set) => entry.card.printing == *set,
+                Test::Colors(letters, exact) => {
+                    let colors = colors(db, entry);
+                    if letters == "C" {
+                        colors.is_empty()
+                    } else {
+                        let has_all = letters.chars().all(|l| colors.iter().any(|c| c.starts_with(l)));
+                        has_all && (!exact || colors.len() == letters.chars().count())
+                    }
+                }
+                Test::Type(t) => entry.card.card.types.iter().any(|c| c.to_lowercase() == *t),
+                Test::Rarity(r) => db.rarity(&name, &entry.card.printing).map_or(false, |c| c.starts_with(r.as_str())),
+                Test::ManaValue(cmp, mv) => db.details(&name).map_or(false, |d| cmp.test(d.mana_value, *mv)),
+                Test::Count(cmp, count) => cmp.test(entry.count, *count),
+                Test::Value(cmp, v) => value(db, entry).map_or(false, |p| cmp.test(p, *v)),
+                Test::Condition(cmp, condition) => cmp.test(entry.get_condition(), *condition),
+                Test::Finish(finish) => entry.get_finish() == *finish,
+                Test::Language(language) => entry.get_language() == *language,
+            };
+            passes != self.negate
+        }
+    }
+
+    // How a list should be shown. Options are written as tokens after the list, e.g.
+    // "sort:color,mv group:type t:creature size:30".
+    #[derive(Clone, Debug)]
+    pub struct ViewOptions {
+        pub sort: Vec<SortKey>,
+        pub group: Option<GroupKey>,
+        pub filters: Vec<Filter>,
+        pub page_size: usize,
+    }
+
+    pub fn new() -> ViewOptions {
+        ViewOptions { sort: Vec::new(), group: None, filters: Vec::new(), page_size: Pager::DEFAULT_PAGE_SIZE }
+    }
+
+    // True if the token looks like an option rather than part of a list name.
+    pub fn is_option(token: &str) -> bool {
+        token.contains([':', '<', '>', '=']) || token.trim_start_matches('-').parse::<CardEntry::Finish>().is_ok()
+    }
+
+    pub fn parse_options<'a, I: IntoIterator<Item = &'a str>>(tokens: I) -> Result<ViewOptions, String> {
+        let mut digest = new();
+        for token in tokens {
+            if let Some(size) = Pager::parse_page_size(token) {
+                digest.page_size = size;
+            } else if let Some(keys) = token.strip_prefix("sort:") {
+                for key in keys.split(',').filter(|k| !k.is_empty()) {
+                    let key = key.parse().map_err(|_| {
+                        format!("'{}' is not something that can be sorted by. Try name, set, color, type, mv, count or value", key)
+                    })?;
+                    digest.sort.push(key);
+                }
+            } else if let Some(key) = token.strip_prefix("group:") {
+                digest.group = Some(key.parse().map_err(|_| {
+                    format!("'{}' is not something that can be grouped by. Try set, color, type or rarity", key)
+                })?);
+            } else {
+                digest.filters.push(token.parse()?);
+            }
+        }
+        Ok(digest)
+    }
+
+    const COLOR_NAMES: [(&str, &str); 5] = [("W", "White"), ("U", "Blue"), ("B", "Black"), ("R", "Red"), ("G", "Green")];
+
+    // Cards are grouped and sorted by their most telling type, e.g. an artifact creature is
+    // a creature.
+    const TYPE_ORDER: [&str; 9] = ["Creature", "Planeswalker", "Battle", "Instant", "Sorcery", "Artifact", "Enchantment", "Land", "Tribal"];
+
+    fn main_type(entry: &CardEntry::CardEntry) -> (usize, String) {
+        let types = &entry.card.card.types;
+        TYPE_ORDER
+            .iter()
+            .enumerate()
+            .find(|(_, t)| types.iter().any(|c| c == *t))
+            .map(|(i, t)| (i, t.to_string()))
+            .unwrap_or_else(|| (TYPE_ORDER.len(), types.first().cloned().unwrap_or_else(|| String::from("Other"))))
+    }
+
+    // Mono colors in WUBRG order, then multicolor, then colorless.
+    fn color_rank(db: &CardDB::CardDB, entry: &CardEntry::CardEntry) -> (usize, String) {
+        let colors = colors(db, entry);
+        match colors.len() {
+            0 => (7, String::from("Colorless")),
+            1 => COLOR_NAMES
+                .iter()
+                .position(|(c, _)| *c == colors[0])
+                .map_or((5, colors[0].clone()), |i| (i, COLOR_NAMES[i].1.to_string())),
+            _ => (6, String::from("Multicolor")),
+        }
+    }
+
+    fn set_rank(db: &CardDB::CardDB, entry: &CardEntry::CardEntry) -> (String, String) {
+        let printing = &entry.card.printing;
+        if printing.is_empty() {
+            return (String::from("~"), String::from("Any printing"));
+        }
+        let name = db.set_name(printing).cloned().unwrap_or_else(|| printing.clone());
+        // Sets sort by release date when it is known
+        (db.release_date(printing).cloned().unwrap_or_else(|| printing.clone()), name)
+    }
+
+    const RARITY_ORDER: [&str; 6] = ["mythic", "rare", "uncommon", "common", "special", "bonus"];
+
+    fn rarity_rank(db: &CardDB::CardDB, entry: &CardEntry::CardEntry) -> (usize, String) {
+        match db.rarity(&entry.card.get_name(), &entry.card.printing) {
+            Some(r) => {
+                let rank = RARITY_ORDER.iter().position(|o| o == r).unwrap_or(RARITY_ORDER.len());
+                let mut name = r.clone();
+                if let Some(first) = name.get_mut(..1) {
+                    first.make_ascii_uppercase();
+                }
+                (rank, name)
+            }
+            None => (RARITY_ORDER.len() + 1, String::from("Unknown rarity")),
+        }
+    }
+
+    fn compare(db: &CardDB::CardDB, key: SortKey, a: &CardEntry::CardEntry, b: &CardEntry::CardEntry) -> Ordering {
+        match key {
+            SortKey::Name => a.card.get_name().cmp(&b.card.get_name()),
+            SortKey::Set => set_rank(db, a).cmp(&set_rank(db, b)),
+            SortKey::Color => color_rank(db, a).0.cmp(&color_rank(db, b).0),
+            SortKey::Type => main_type(a).0.cmp(&main_type(b).0),
+            SortKey::ManaValue => {
+                let mv = |e: &CardEntry::CardEntry| db.details(&e.card.get_name()).map_or(0.0, |d| d.mana_value);
+                mv(a).partial_cmp(&mv(b)).unwrap_or(Ordering::Equal)
+            }
+            // Larger counts and values first, since those are what people look for
+            SortKey::Count => b.count.cmp(&a.count),
+            SortKey::Value => value(db, b).unwrap_or(0.0).partial_cmp(&value(db, a).unwrap_or(0.0)).unwrap_or(Ordering::Equal),
+        }
+    }
+
+    fn group_of(db: &CardDB::CardDB, key: GroupKey, entry: &CardEntry::CardEntry) -> (String, String) {
+        // The first part orders the groups, the second part names them
+        match key {
+            GroupKey::Set => set_rank(db, entry),
+            GroupKey::Color => {
+                let (rank, name) = color_rank(db, entry);
+                (format!("{}", rank), name)
+            }
+            GroupKey::Type => {
+                let (rank, name) = main_type(entry);
+                (format!("{:02}{}", rank, name), name)
+            }
+            GroupKey::Rarity => {
+                let (rank, name) = rarity_rank(db, entry);
+                (format!("{}", rank), name)
+            }
+        }
+    }
+
+    fn line(entry: &CardEntry::CardEntry) -> String {
+        if entry.reserved > 0 {
+            format!("{} ({} held for trades)", entry, entry.reserved)
+        } else {
+            entry.to_string()
+        }
+    }
+
+    // Filters, sorts and groups the list into (group name, lines). Ties are broken by name
+    // and then by the entry itself, so the same list always shows the same way.
+    pub fn lines(db: &CardDB::CardDB, list: &CardList::CardList, title: &str, options: &ViewOptions) -> Vec<(String, Vec<String>)> {
+        let mut entries: Vec<&CardEntry::CardEntry> = list
+            .iter()
+            .filter(|e| options.filters.iter().all(|f| f.accepts(db, e)))
+            .collect();
+        entries.sort_by(|a, b| {
+            options
+                .sort
+                .iter()
+                .chain(&[SortKey::Name])
+                .map(|k| compare(db, *k, a, b))
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or_else(|| a.to_string().cmp(&b.to_string()))
+        });
+
+        let group = match options.group {
+            Some(g) => g,
+            None => return vec![(title.to_string(), entries.iter().map(|e| line(e)).collect())],
+        };
+        let mut groups: Vec<((String, String), Vec<String>)> = Vec::new();
+        for entry in entries {
+            let key = group_of(db, group, entry);
+            match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, lines)) => lines.push(line(entry)),
+                None => groups.push((key, vec![line(entry)])),
+            }
+        }
+        groups.sort_by(|a, b| a.0.cmp(&b.0));
+        if groups.is_empty() {
+            return vec![(title.to_string(), Vec::new())];
+        }
+        groups
+            .into_iter()
+            .map(|((_, name), lines)| (format!("{}: {}", title, name), lines))
+            .collect()
+    }
+}
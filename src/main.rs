@@ -8,62 +8,120 @@
 //! git = "https://github.com/serenity-rs/serenity.git"
 //! features = ["framework", "standard_framework"]
 //! ```
+#![allow(non_snake_case)]
 
 mod response;
 mod card;
 mod card_db;
 mod card_entry;
+mod card_list;
+mod collectible;
+mod collection;
+mod decklist;
+mod collection_csv;
+mod export;
+mod ledger;
+mod list_view;
+mod migrations;
+mod notifier;
+mod pager;
+mod profile;
+mod proposal;
+mod reputation;
+mod session;
+mod settings;
+mod storage;
+mod trade_store;
+#[cfg(feature = "sqlite")]
+mod sqlite_store;
 mod tradelist;
+mod trader;
 mod utils;
 
-use std::{
-    collections::{HashMap, HashSet},
-    env,
-    fmt::Write,
-    sync::{Arc, RwLock},
-};
+use std::{collections::{HashMap, HashSet}, env, path::PathBuf, sync::Arc, time::Duration};
 
 use serenity::prelude::*;
 use serenity::{
     async_trait,
-    client::bridge::gateway::{GatewayIntents, ShardId, ShardManager},
+    client::bridge::gateway::GatewayIntents,
     framework::standard::{
-        buckets::{LimitedFor, RevertBucket},
+        buckets::LimitedFor,
         help_commands,
-        macros::{check, command, group, help, hook},
-        Args, CommandGroup, CommandOptions, CommandResult, Delimiter, DispatchError, HelpOptions,
-        Reason, StandardFramework,
+        macros::{command, group, help, hook},
+        Args, CommandGroup, CommandResult, Delimiter, DispatchError, HelpOptions,
+        StandardFramework,
     },
     http::Http,
-    model::{
-        channel::{Channel, Message, Embed, EmbedField},
-        gateway::Ready,
-        id::UserId,
-        permissions::Permissions,
-    },
-    utils::{content_safe, ContentSafeOptions, Colour},
+    utils::Colour,
+    model::{channel::{Message, Reaction}, gateway::Ready, id::{GuildId, UserId}},
 };
-use tokio::sync::Mutex;
 use dashmap::DashMap;
 
 pub use response::Response;
 pub use card::Card;
 pub use card_db::CardDB;
 pub use card_entry::CardEntry;
+pub use card_list::CardList;
+pub use collectible::Collectible;
+pub use collection::Collection;
+pub use decklist::Decklist;
+pub use collection_csv::CollectionCsv;
+pub use export::Export;
+pub use ledger::Ledger;
+pub use list_view::ListView;
+pub use migrations::Migrations;
+pub use notifier::Notifier;
+pub use pager::Pager;
+pub use profile::Profile;
+pub use proposal::Proposal;
+pub use reputation::Reputation;
+pub use session::Session;
+pub use settings::Settings;
+pub use storage::Storage;
+pub use trade_store::TradeStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite_store::SqliteStore;
 pub use tradelist::Tradelist;
+pub use trader::Trader;
 pub use utils::*;
 
 struct Handler;
 
+// Where an import is going: the haves of one of the user's lists (the default one if no
+// name was given), or their collection.
+enum ImportTarget {
+    List(Option<String>),
+    Collection,
+}
+
+// Imports that have been previewed but not yet added, along with where they are going.
+struct PendingImport;
+
+impl TypeMapKey for PendingImport {
+    type Value = DashMap<UserId, (ImportTarget, Vec<CardEntry::CardEntry>)>;
+}
+
+// How far matches and searches look for partners unless told otherwise, in direct messages
+// and guilds that haven't chosen their own with '!settings scope'. Set with TAVERN_SCOPE.
+struct DefaultScope;
+
+impl TypeMapKey for DefaultScope {
+    type Value = Profile::Scope;
+}
+
 #[async_trait]
 impl EventHandler for Handler {
     async fn ready(&self, _: Context, ready: Ready) {
         println!("{} is connected!", ready.user.name);
     }
+
+    async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        Pager::turn_page(&ctx, &reaction).await;
+    }
 }
 
 #[group]
-#[commands(printings, tradelist)]
+#[commands(printings, tradelist, collection, matches, whohas, compare, trade, reputation, profile, lft, settings)]
 struct General;
 
 // The framework provides two built-in help commands for you to use.
@@ -106,7 +164,7 @@ async fn my_help(
 }
 
 #[hook]
-async fn before(ctx: &Context, msg: &Message, command_name: &str) -> bool {
+async fn before(_ctx: &Context, msg: &Message, command_name: &str) -> bool {
     println!(
         "Got command '{}' by user '{}'",
         command_name, msg.author.name
@@ -181,6 +239,17 @@ fn _dispatch_error_no_macro<'fut>(
 
 #[tokio::main]
 async fn main() {
+    // Tradelists are saved to this file so that they survive restarts.
+    let storage = Arc::new(Storage::new(PathBuf::from(
+        env::var("TAVERN_DATA").unwrap_or_else(|_| String::from("tradelists.json")),
+    )));
+
+    // With TAVERN_DRY_RUN set, report which migrations would run and exit without changing anything.
+    if env::var("TAVERN_DRY_RUN").is_ok() {
+        dry_run(&storage);
+        return;
+    }
+
     // Configure the client with your Discord bot token in the environment.
     let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
 
@@ -279,15 +348,139 @@ async fn main() {
         .await
         .expect("Err creating client");
 
+    let store = open_store(&storage).await;
+    let notifications = Arc::new(Notifier::new());
+
     {
         let mut data = client.data.write().await;
         data.insert::<CardDB::CardDB>(CardDB::create(String::from("AtomicCards.json")));
-        data.insert::<Tradelist::Tradelist>(DashMap::new());
+        data.insert::<TradeStore::Store>(store.clone());
+        data.insert::<PendingImport>(DashMap::new());
+        data.insert::<Pager::Paginated>(DashMap::new());
+        data.insert::<Storage::Storage>(storage.clone());
+        data.insert::<DefaultScope>(default_scope());
+        data.insert::<Session::Sessions>(DashMap::new());
+        data.insert::<Notifier::Queue>(notifications.clone());
     }
 
+    // Tell users about new matches for their lists, a batch at a time.
+    let notifying_store = store.clone();
+    let http = client.cache_and_http.http.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Notifier::FLUSH_INTERVAL);
+        loop {
+            interval.tick().await;
+            Notifier::flush(&http, notifying_store.as_ref(), &notifications).await;
+        }
+    });
+
+    // Let go of the cards held by expired proposals every minute.
+    let expiring_store = store.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            if let Err(why) = expire_proposals(expiring_store.as_ref()) {
+                println!("Could not expire proposals: {}", why);
+            }
+        }
+    });
+
+    // Snapshot the tradelists every minute. Nothing is written if nothing has changed.
+    if !store.is_persistent() {
+        let periodic_store = store.clone();
+        let periodic_storage = storage.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                if let Err(why) = periodic_storage.save(periodic_store.as_ref()).await {
+                    println!("Could not save the tradelists: {:?}", why);
+                }
+            }
+        });
+    }
+
+    // Shut down cleanly on ctrl-c so that the final snapshot below is written.
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(async move {
+        tokio::signal::ctrl_c().await.expect("Could not register the ctrl-c handler");
+        shard_manager.lock().await.shutdown_all().await;
+    });
+
     if let Err(why) = client.start().await {
         println!("Client error: {:?}", why);
     }
+
+    if !store.is_persistent() {
+        if let Err(why) = storage.save(store.as_ref()).await {
+            println!("Could not save the tradelists: {:?}", why);
+        }
+    }
+}
+
+fn dry_run(storage: &Storage::Storage) {
+    let report = |name: String, applied: Result<Vec<String>, String>| match applied {
+        Ok(steps) if steps.is_empty() => println!("{} is up to date.", name),
+        Ok(steps) => {
+            println!("{} would be migrated:", name);
+            for step in steps {
+                println!("  {}", step);
+            }
+        }
+        Err(why) => println!("{} could not be migrated: {}", name, why),
+    };
+
+    let applied = storage
+        .migrate(true)
+        .map(|m| m.map(|(_, steps)| steps).unwrap_or_default())
+        .map_err(|e| e.to_string());
+    report(storage.path().display().to_string(), applied);
+
+    #[cfg(feature = "sqlite")]
+    if let Ok(path) = env::var("TAVERN_SQLITE") {
+        report(path.clone(), SqliteStore::pending(std::path::Path::new(&path)));
+    }
+}
+
+// Tradelists are kept in memory and snapshotted by default. If the bot was built with the
+// sqlite feature and TAVERN_SQLITE names a database, they are kept there instead.
+async fn open_store(storage: &Storage::Storage) -> Arc<dyn TradeStore::TradeStore> {
+    #[cfg(feature = "sqlite")]
+    if let Ok(path) = env::var("TAVERN_SQLITE") {
+        let store = SqliteStore::open(std::path::Path::new(&path))
+            .unwrap_or_else(|why| panic!("Could not open {}: {}", path, why));
+        println!("Using the SQLite database {}.", path);
+        // A new database starts out with whatever was in the json snapshot
+        if TradeStore::TradeStore::all(&store).map_or(false, |lists| lists.is_empty()) {
+            if let Ok((tradelists, proposals, guilds)) = storage.load().await {
+                for list in tradelists.iter() {
+                    if let Err(why) = store.put(*list.key(), list.value()) {
+                        println!("Could not move a tradelist into {}: {}", path, why);
+                    }
+                }
+                for proposal in &proposals {
+                    if let Err(why) = store.put_proposal(proposal) {
+                        println!("Could not move proposal #{} into {}: {}", proposal.id, path, why);
+                    }
+                }
+                for (guild, settings) in &guilds {
+                    if let Err(why) = store.put_settings(*guild, settings) {
+                        println!("Could not move the settings of guild {} into {}: {}", guild, path, why);
+                    }
+                }
+                println!("Moved {} tradelists and {} proposals into {}.", tradelists.len(), proposals.len(), path);
+            }
+        }
+        return Arc::new(store);
+    }
+
+    let (tradelists, proposals, guilds) = storage
+        .load()
+        .await
+        .unwrap_or_else(|why| panic!("Could not load {}: {:?}", storage.path().display(), why));
+    println!("Loaded {} tradelists and {} proposals.", tradelists.len(), proposals.len());
+    Arc::new(TradeStore::memory(tradelists, proposals, guilds))
 }
 
 #[command("printings")]
@@ -327,126 +520,643 @@ async fn printings(ctx: &Context, msg: &Message, mut args: Args) -> CommandResul
     Ok(())
 }
 
-fn create_entries(db: &CardDB::CardDB, args: &str) -> Vec<CardEntry::CardEntry> {
-    let mut digest = Vec::new();
-    let mut entries: Args = Args::new(args, &[Delimiter::Single('\n')]);
+// Parses a pasted list of cards, one per line. Lines that can't be understood are returned
+// along side the entries so they can be reported back to the user.
+fn create_entries(db: &CardDB::CardDB, args: &str) -> (Vec<CardEntry::CardEntry>, Vec<String>) {
+    let list = Decklist::parse(db, args);
+    let digest = list.entries.into_iter().map(|(_, e)| e).collect();
+    let errors = list.errors.iter().map(|e| e.to_string()).collect();
+    (digest, errors)
+}
 
-    let mut curr_entry = entries.single::<String>();
-    let mut entry_args: Args;
-    let mut entry_quantity: Types::CardCount;
-    let mut entry_name: String;
-    let mut entry_card: Option<&Card::AtomicCard>;
+// Commands can name one of the user's lists in quotes before anything else, e.g.
+// '!tradelist add "Bulk rares" 4 Lightning Bolt'. Returns the name, if there is one, and
+// the rest of the text. Phones often type curly quotes, so those work too.
+fn split_list_name(text: &str) -> (Option<&str>, &str) {
+    let trimmed = text.trim_start();
+    if let Some(rest) = trimmed.strip_prefix(['"', '“']) {
+        if let Some((end, quote)) = rest.char_indices().find(|(_, c)| ['"', '”'].contains(c)) {
+            return (Some(&rest[..end]), &rest[end + quote.len_utf8()..]);
+        }
+    }
+    (None, text)
+}
 
-    while !curr_entry.is_err() {
-        entry_args = Args::new(&curr_entry.unwrap(), &[Delimiter::Single(' ')]);
-        entry_quantity = entry_args.single::<Types::CardCount>().unwrap();
-        entry_name = String::from(entry_args.rest());
-        entry_card = db.get_card(&entry_name);
-        if entry_card.is_none() {
-            ();
-        } else {
-            digest.push(CardEntry::new(
-                entry_quantity,
-                Card::new(entry_card.unwrap().clone(), String::from("")),
-            ));
+// For commands that only take a list name, the quotes are optional.
+fn list_arg(text: &str) -> Option<&str> {
+    match split_list_name(text) {
+        (Some(name), _) => Some(name),
+        (None, rest) if !rest.trim().is_empty() => Some(rest.trim()),
+        _ => None,
+    }
+}
+
+// Logs a problem with the trade store and lets the user know something went wrong.
+fn store_error(digest: &mut Response::Response, why: String) {
+    println!("Trade store error: {}", why);
+    digest.set_content( String::from("Something went wrong while reading or saving tradelists. Please try again later.") );
+}
+
+const NO_TRADELIST: &str = "You do not have a tradelist. To add one, just use the the command '!tradelist add' followed by a quantity and card name.";
+
+// Shows one of the user's lists a page at a time, e.g.
+// '!tradelist view "Bulk rares" sort:color,mv group:type t:creature -foil size:30'.
+async fn view_tradelist(ctx: &Context, msg: &Message, args: Args) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let db = data.get::<CardDB::CardDB>().unwrap();
+
+    // Without quotes, the list name is whatever isn't an option
+    let (quoted, rest) = split_list_name(args.rest());
+    let (words, options): (Vec<&str>, Vec<&str>) = rest.split_whitespace().partition(|t| quoted.is_none() && !ListView::is_option(t));
+    let name = quoted.map(String::from).or_else(|| Some(words.join(" ")).filter(|n| !n.is_empty()));
+    let options = match ListView::parse_options(options) {
+        Ok(o) => o,
+        Err(why) => {
+            digest.set_content(why);
+            return digest;
+        }
+    };
+
+    match store.get(msg.author.id) {
+        Ok(Some(trader)) => match trader.resolve(name.as_deref()) {
+            Ok(name) => {
+                let list = trader.get(Some(&name)).unwrap();
+                digest.set_pages( list.get_pages(db, &name, &options) );
+            }
+            Err(why) => digest.set_content(why),
+        },
+        Ok(None) => digest.set_content( String::from("You don't have a tradelist. Use '!tradelist add' to add some cards first.") ),
+        Err(why) => store_error(&mut digest, why),
+    }
+
+    digest
+}
+
+// Queues new haves and wants to be announced to the users they match.
+fn notify(data: &TypeMap, added: Vec<Notifier::Addition>) {
+    let queue = data.get::<Notifier::Queue>().unwrap();
+    for addition in added {
+        queue.push(addition);
+    }
+}
+
+// Changing a collection can drop held copies out of the linked list. Tells the user which
+// ones, and the other side of each proposal that held them that it may not go through.
+fn lost_holds_warning(store: &dyn TradeStore::TradeStore, user: UserId, lost: &[CardEntry::CardEntry]) -> String {
+    if lost.is_empty() {
+        return String::new();
+    }
+    let mut digest = format!("\n<@{}>, copies held for trade proposals are no longer in your haves: {}.", user, Proposal::describe_cards(lost));
+    for p in store.proposals_for(user).unwrap_or_default().iter().filter(|p| p.holds_cards()) {
+        let (held, other) = if p.from == user { (&p.give, p.to) } else { (&p.get, p.from) };
+        if p.from != user && p.status() != Proposal::Status::Accepted {
+            continue;
+        }
+        if held.iter().any(|h| lost.iter().any(|l| l.satisfies(h))) {
+            digest += &format!("\n<@{}>, proposal #{} may not go through, since <@{}> may no longer have all of the cards.", other, p.id, user);
+        }
+    }
+    digest
+}
+
+// Adds cards to either the haves or the wants of one of the user's lists.
+async fn add_to_tradelist(ctx: &Context, msg: &Message, args: Args, is_want: bool) -> Response::Response {
+    println!("Adding cards to the tradelist.");
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let db = data.get::<CardDB::CardDB>().unwrap();
+    let (list_name, text) = split_list_name(args.rest());
+    let (mut entries, mut errors) = create_entries(db, text);
+
+    let mut content = match store.get(msg.author.id) {
+        Ok(Some(_)) => String::from("Your tradelist has been updated. Use '!tradelist view' to see it."),
+        Ok(None) => String::from("You have added a tradelist with some cards. To see it, use the command '!tradelist view'."),
+        Err(why) => {
+            store_error(&mut digest, why);
+            return digest;
+        }
+    };
+
+    let mut failure = None;
+    let mut added = None;
+    let result = store.update(msg.author.id, true, &mut |trader| {
+        let name = trader.resolve(list_name).unwrap_or_default();
+        let list = match if is_want { trader.get_mut(list_name) } else { trader.get_haves_mut(list_name) } {
+            Ok(l) => l,
+            Err(why) => {
+                failure = Some(why);
+                return;
+            }
+        };
+        let mut cards = Vec::new();
+        for entry in entries.drain(..) {
+            if is_want {
+                cards.push(entry.clone());
+                list.add_want(entry);
+            } else if entry.card.pattern.is_some() {
+                errors.push(format!("{} is a printing pattern, which can only be used for wants", entry.card));
+            } else {
+                cards.push(entry.clone());
+                list.add_card(entry);
+            }
+        }
+        if list.is_public() {
+            added = Some(Notifier::Addition { owner: msg.author.id, list: name, is_want, cards });
+        }
+    });
+    if let Err(why) = result {
+        store_error(&mut digest, why);
+        return digest;
+    }
+    if let Some(why) = failure {
+        digest.set_content(why);
+        return digest;
+    }
+    notify(&data, added.into_iter().collect());
+    if !errors.is_empty() {
+        content += "\nThe following lines could not be added:";
+        for e in errors {
+            content += &format!("\n- {}", e);
         }
-        curr_entry = entries.single::<String>();
     }
+    digest.set_content(content);
     digest
 }
 
-async fn view_tradelist(ctx: &Context, msg: &Message) -> Response::Response {
+// Removes cards from either the haves or the wants of one of the user's lists.
+async fn remove_from_tradelist(ctx: &Context, msg: &Message, args: Args, is_want: bool) -> Response::Response {
+    println!("Removing cards from the tradelist.");
     let mut digest: Response::Response = Response::new();
     let data = ctx.data.read().await;
-    let tradelists = data.get::<Tradelist::Tradelist>().unwrap();
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let db = data.get::<CardDB::CardDB>().unwrap();
+    let (list_name, text) = split_list_name(args.rest());
+    let (entries, mut errors) = create_entries(db, text);
+    let kind = if is_want { "wants" } else { "haves" };
+
+    let mut removed: Vec<String> = Vec::new();
+    let mut failure = None;
+    let result = store.update(msg.author.id, false, &mut |trader| {
+        let list = match if is_want { trader.get_mut(list_name) } else { trader.get_haves_mut(list_name) } {
+            Ok(l) => l,
+            Err(why) => {
+                failure = Some(why);
+                return;
+            }
+        };
+        for entry in &entries {
+            let count = if is_want {
+                list.remove_want(entry.clone())
+            } else {
+                list.remove_card(entry.clone())
+            };
+            let mut shown = entry.clone();
+            shown.update_count(count);
+            // Copies held by trade proposals can't be removed
+            let held: Types::CardCount = if is_want { 0 } else { list.haves().get(&entry.card.get_name()).filter(|c| entry.covers(c)).map(|c| c.reserved).sum() };
+            if count == 0 && held > 0 {
+                errors.push(format!("{} is held for a trade proposal. Withdraw or cancel the proposal first", entry.card));
+            } else if count == 0 {
+                errors.push(format!("{} is not in your {}", entry.card, kind));
+            } else if count < entry.count && held > 0 {
+                removed.push(format!("{} ({} more are held for trade proposals)", shown, held));
+            } else if count < entry.count {
+                removed.push(format!("{} (you only had {})", shown, count));
+            } else {
+                removed.push(shown.to_string());
+            }
+        }
+    });
+    match result {
+        Ok(true) => {}
+        Ok(false) => {
+            digest.set_content( String::from(NO_TRADELIST) );
+            return digest;
+        }
+        Err(why) => {
+            store_error(&mut digest, why);
+            return digest;
+        }
+    }
+    if let Some(why) = failure {
+        digest.set_content(why);
+        return digest;
+    }
 
-    if let Some(list) = tradelists.get(&msg.author.id) {
-        println!( "{}", list.to_string() );
-        digest.set_embed( list.get_embed() );
+    let mut content = if removed.is_empty() {
+        format!("Nothing was removed from your {}.", kind)
     } else {
-        digest.set_content( String::from("You don't have a tradelist. Use '!tradelist add' to add some cards first.") );
+        format!("The following cards were removed from your {}:", kind)
+    };
+    for r in removed {
+        content += &format!("\n- {}", r);
+    }
+    if !errors.is_empty() {
+        content += "\nThe following lines were not removed:";
+        for e in errors {
+            content += &format!("\n- {}", e);
+        }
     }
-    
+    digest.set_content(content);
     digest
 }
 
-async fn add_to_tradelist(ctx: &Context, msg: &Message, args: Args) -> Response::Response {
-    println!("Adding cards to the tradelist.");
+// Pulls the text out of the first ``` code block in a message, if there is one. An optional
+// language tag (e.g. ```csv) is dropped.
+fn code_block(text: &str) -> Option<&str> {
+    let start = text.find("```")? + 3;
+    let end = start + text[start..].find("```")?;
+    let block = &text[start..end];
+    match block.find('\n') {
+        Some(i) if !block[..i].trim().contains(' ') && !block[..i].contains(',') => Some(&block[i + 1..]),
+        _ => Some(block),
+    }
+}
+
+// Imports a collection export or decklist, e.g. "!tradelist import moxfield" with the CSV
+// attached, or '!tradelist import "Bulk rares" moxfield' for another list. The import is only
+// previewed until the user confirms it with "!tradelist import confirm", at which point the
+// cards are added to the haves of the list. "!collection import" works the same way, but
+// adds the cards to the user's collection.
+async fn import_to_tradelist(ctx: &Context, msg: &Message, args: Args, to_collection: bool) -> Response::Response {
+    println!("Importing cards to the tradelist.");
     let mut digest: Response::Response = Response::new();
-    let data = ctx.data.write().await;
-    let tradelists = data.get::<Tradelist::Tradelist>().unwrap();
+    let command = if to_collection { "!collection" } else { "!tradelist" };
+    let (list_name, rest) = if to_collection { (None, args.rest()) } else { split_list_name(args.rest()) };
+    let mut args = Args::new(rest, &[Delimiter::Single(' '), Delimiter::Single('\n')]);
+    let format = args.single::<String>().unwrap_or_default().to_lowercase();
+    let data = ctx.data.read().await;
+    let pending = data.get::<PendingImport>().unwrap();
+    let store = data.get::<TradeStore::Store>().unwrap();
+
+    if format == "confirm" {
+        match pending.remove(&msg.author.id) {
+            Some((_, (target, mut entries))) => {
+                let count = entries.len();
+                let mut failure = None;
+                let mut added = Vec::new();
+                let mut lost = Vec::new();
+                let result = store.update(msg.author.id, true, &mut |trader| match &target {
+                    ImportTarget::List(list_name) => {
+                        let name = trader.resolve(list_name.as_deref()).unwrap_or_default();
+                        match trader.get_haves_mut(list_name.as_deref()) {
+                            Ok(list) => {
+                                if list.is_public() {
+                                    added.push(Notifier::Addition { owner: msg.author.id, list: name, is_want: false, cards: entries.clone() });
+                                }
+                                for entry in entries.drain(..) {
+                                    list.add_card(entry);
+                                }
+                            }
+                            Err(why) => failure = Some(why),
+                        }
+                    }
+                    ImportTarget::Collection => {
+                        added = Notifier::watch(msg.author.id, trader, |t| {
+                            t.update_collection(|c| {
+                                for entry in entries.drain(..) {
+                                    c.add(entry);
+                                }
+                            })
+                        });
+                        lost = trader.take_lost_holds();
+                    }
+                });
+                if let (Ok(_), None) = (&result, &failure) {
+                    notify(&data, added);
+                }
+                match (result, failure, target) {
+                    (Err(why), _, _) => store_error(&mut digest, why),
+                    (Ok(_), Some(why), _) => digest.set_content(why),
+                    (Ok(_), None, ImportTarget::Collection) => digest.set_content( format!("{} entries have been added to your collection. Use '!collection view' to see it.{}", count, lost_holds_warning(store.as_ref(), msg.author.id, &lost)) ),
+                    (Ok(_), None, _) => digest.set_content( format!("{} entries have been added to your tradelist. Use '!tradelist view' to see it.", count) ),
+                }
+            }
+            None => digest.set_content( String::from("You don't have an import waiting to be confirmed.") ),
+        }
+        return digest;
+    } else if format == "cancel" {
+        pending.remove(&msg.author.id);
+        digest.set_content( String::from("Your import has been cancelled.") );
+        return digest;
+    }
+
+    // Catch a misspelled or linked list now rather than when the import is confirmed
+    if !to_collection {
+        match store.get(msg.author.id) {
+            Ok(Some(mut trader)) => {
+                if let Err(why) = trader.get_haves_mut(list_name) {
+                    digest.set_content(why);
+                    return digest;
+                }
+            }
+            Ok(None) if list_name.is_some() => {
+                digest.set_content( format!("You don't have a list called \"{}\". Use '!tradelist create' to make a new list.", list_name.unwrap_or_default()) );
+                return digest;
+            }
+            Ok(None) => {}
+            Err(why) => {
+                store_error(&mut digest, why);
+                return digest;
+            }
+        }
+    }
+
+    let text = if let Some(attachment) = msg.attachments.first() {
+        match attachment.download().await {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).to_string(),
+            Err(why) => {
+                digest.set_content( format!("The attachment could not be downloaded: {}", why) );
+                return digest;
+            }
+        }
+    } else if let Some(block) = code_block(&msg.content) {
+        block.to_string()
+    } else {
+        args.rest().to_string()
+    };
+
     let db = data.get::<CardDB::CardDB>().unwrap();
-    let entries = create_entries(db, args.rest());
+    let (entries, errors) = if let Ok(f) = format.parse::<CollectionCsv::Format>() {
+        CollectionCsv::parse(db, f, &text)
+    } else if matches!(format.as_str(), "text" | "arena" | "mtga" | "mtgo") {
+        let list = Decklist::parse(db, &text);
+        (list.entries.into_iter().map(|(_, e)| e).collect(), list.errors)
+    } else {
+        digest.set_content( String::from("You need to say what format you are importing: deckbox, moxfield, tcgplayer, manabox, or text.") );
+        return digest;
+    };
 
-    if let Some(list) = tradelists.get_mut(&msg.author.id) {
-        digest.set_content( String::from("Your tradelist has been updated. Use '!tradelist view' to see it.") );
+    let total: Types::CardCount = entries.iter().map(|e| e.count).sum();
+    let mut content = format!("Found {} cards in {} entries.", total, entries.len());
+    for e in entries.iter().take(10) {
+        content += &format!("\n- {}", e);
+    }
+    if entries.len() > 10 {
+        content += &format!("\n- ...and {} more", entries.len() - 10);
+    }
+    if !errors.is_empty() {
+        content += &format!("\n{} lines could not be read:", errors.len());
+        for e in errors.iter().take(10) {
+            content += &format!("\n- {}", e);
+        }
+    }
+    if entries.is_empty() {
+        content += "\nThere is nothing to import.";
     } else {
-        tradelists
-            .insert(msg.author.id, Tradelist::new());
-        digest.set_content( String::from("You have added a tradelist with some cards. To see it, use the command '!tradelist view'.") );
+        content += &format!("\nUse '{} import confirm' to add these or '{} import cancel' to throw them away.", command, command);
+        let target = if to_collection { ImportTarget::Collection } else { ImportTarget::List(list_name.map(String::from)) };
+        pending.insert(msg.author.id, (target, entries));
+    }
+    digest.set_content(content);
+    digest
+}
+
+// Sends the user's haves (or wants) as a file, e.g. "!tradelist export moxfield" or
+// '!tradelist export "Bulk rares" text wants'. The format defaults to text.
+async fn export_tradelist(ctx: &Context, msg: &Message, args: Args) -> Response::Response {
+    println!("Exporting the tradelist.");
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let db = data.get::<CardDB::CardDB>().unwrap();
+    let (list_name, rest) = split_list_name(args.rest());
+    let mut args = Args::new(rest, &[Delimiter::Single(' '), Delimiter::Single('\n')]);
+
+    let mut format = Export::Format::Text;
+    let mut is_want = false;
+    while let Ok(arg) = args.single::<String>() {
+        if arg.eq_ignore_ascii_case("wants") {
+            is_want = true;
+        } else if let Ok(f) = arg.parse::<Export::Format>() {
+            format = f;
+        } else {
+            digest.set_content( format!("'{}' is not a format that can be exported to. Try text, arena, deckbox, moxfield, or json.", arg) );
+            return digest;
+        }
     }
 
-    for entry in entries {
-        tradelists
-            .get_mut(&msg.author.id)
-            .unwrap()
-            .add_card(entry);
+    match store.get(msg.author.id) {
+        Ok(Some(trader)) => match trader.get(list_name) {
+            Ok(list) => {
+                let (cards, name) = if is_want { (list.wants(), "wants") } else { (list.haves(), "haves") };
+                match Export::export(db, cards, format, name) {
+                    Ok((filename, file)) => {
+                        digest.set_content( format!("Here are your {}.", name) );
+                        digest.add_file(filename, file);
+                    }
+                    Err(why) => digest.set_content( format!("Your tradelist could not be exported: {}", why) ),
+                }
+            }
+            Err(why) => digest.set_content(why),
+        },
+        Ok(None) => digest.set_content( String::from("You don't have a tradelist. Use '!tradelist add' to add some cards first.") ),
+        Err(why) => store_error(&mut digest, why),
     }
     digest
 }
 
-async fn remove_from_tradelist(ctx: &Context, msg: &Message, mut args: Args) -> Response::Response {
+async fn set_tradelist_visibility(ctx: &Context, msg: &Message, args: Args, is_public: bool) -> Response::Response {
+    println!("Making the tradelist {}.", if is_public { "public" } else { "private" });
     let mut digest: Response::Response = Response::new();
     let data = ctx.data.read().await;
-    let list = data.get::<Tradelist::Tradelist>().unwrap();
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let mut outcome = Err(String::new());
+    let mut added = Vec::new();
+    let result = store.update(msg.author.id, false, &mut |trader| {
+        // Making a list public puts all of its cards up at once
+        added = Notifier::watch(msg.author.id, trader, |trader| {
+            outcome = trader.resolve(list_arg(args.rest())).map(|name| {
+                let list = trader.get_mut(Some(&name)).unwrap();
+                if is_public {
+                    list.set_public();
+                } else {
+                    list.set_private();
+                }
+                name
+            });
+        });
+    });
+    if let (Ok(true), Ok(_)) = (&result, &outcome) {
+        notify(&data, added);
+    }
+    match (result, outcome) {
+        (Err(why), _) => store_error(&mut digest, why),
+        (Ok(false), _) => digest.set_content( String::from(NO_TRADELIST) ),
+        (Ok(true), Err(why)) => digest.set_content(why),
+        (Ok(true), Ok(name)) if is_public => digest.set_content( format!("\"{}\" has been set to public. It **will** be found during tradelist searches.", name) ),
+        (Ok(true), Ok(name)) => digest.set_content( format!("\"{}\" has been set to private. It **will not** be found during tradelist searches.", name) ),
+    }
     digest
 }
 
-async fn make_public_tradelist(ctx: &Context, msg: &Message) -> Response::Response {
-    println!("Making the tradelist public.");
+// Shows every list the user has, with its visibility and size.
+async fn show_lists(ctx: &Context, msg: &Message) -> Response::Response {
     let mut digest: Response::Response = Response::new();
     let data = ctx.data.read().await;
-    let tradelists = data.get::<Tradelist::Tradelist>().unwrap();
-    if let Some(mut list) = tradelists.get_mut(&msg.author.id) {
-        list.set_public();
-        digest.set_content( String::from("Your tradelist has been set to public. Your tradelist **will** be found during tradelist searches.") );
-    } else {
-        digest.set_content( String::from("You do not have a tradelist. To add one, just use the the command '!tradelist add' followed by a quantity and card name.") );
+    let store = data.get::<TradeStore::Store>().unwrap();
+    match store.get(msg.author.id) {
+        Ok(Some(trader)) => {
+            let mut content = String::from("Your lists:");
+            for (name, list) in trader.lists() {
+                let default = if name == trader.default_name() { "default, " } else { "" };
+                let visibility = if list.is_public() { "public" } else { "private" };
+                content += &format!(
+                    "\n- \"{}\" ({}{}): {} haves, {} wants",
+                    name, default, visibility, list.haves().card_count(), list.wants().card_count()
+                );
+            }
+            digest.set_content(content);
+        }
+        Ok(None) => digest.set_content( String::from(NO_TRADELIST) ),
+        Err(why) => store_error(&mut digest, why),
+    }
+    digest
+}
+
+// Creates, renames, deletes or picks the default list, e.g. "!tradelist create Bulk rares"
+// or '!tradelist rename "Bulk rares" Bulk'.
+async fn manage_lists(ctx: &Context, msg: &Message, task: &str, args: Args) -> Response::Response {
+    println!("Managing lists: {}", task);
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let text = args.rest();
+    let mut outcome = Err(String::new());
+    let result = store.update(msg.author.id, task == "create", &mut |trader| {
+        outcome = match task {
+            "create" => trader.create(list_arg(text).unwrap_or_default())
+                .map(|name| format!("\"{}\" has been created. Add cards to it with '!tradelist add \"{}\"' followed by the cards.", name, name)),
+            "rename" => match split_list_name(text) {
+                (Some(old), new) => trader.rename(old, list_arg(new).unwrap_or_default())
+                    .map(|old| format!("\"{}\" has been renamed to \"{}\".", old, list_arg(new).unwrap_or_default().trim())),
+                (None, _) => Err(String::from("Put the name of the list to rename in quotes, e.g. '!tradelist rename \"Bulk rares\" Bulk'.")),
+            },
+            "delete" => match list_arg(text) {
+                Some(name) => trader.delete(name).map(|list| {
+                    format!("The list has been deleted. It had {} haves and {} wants.", list.haves().card_count(), list.wants().card_count())
+                }),
+                None => Err(String::from("You need to say which list to delete.")),
+            },
+            _ => match list_arg(text) {
+                Some(name) => trader.set_default(name).map(|name| format!("\"{}\" is now your default list.", name)),
+                None => Err(String::from("You need to say which list should be the default.")),
+            },
+        };
+    });
+    match (result, outcome) {
+        (Err(why), _) => store_error(&mut digest, why),
+        (Ok(false), _) => digest.set_content( String::from(NO_TRADELIST) ),
+        (Ok(true), Err(why)) | (Ok(true), Ok(why)) => digest.set_content(why),
     }
     digest
 }
 
-async fn make_private_tradelist(ctx: &Context, msg: &Message) -> Response::Response {
-    println!("Making the tradelist private.");
+// Moves cards between two lists, e.g. '!tradelist move "Main" "Bulk rares" 4 Lightning Bolt'.
+async fn move_between_lists(ctx: &Context, msg: &Message, args: Args, is_want: bool) -> Response::Response {
+    println!("Moving cards between lists.");
     let mut digest: Response::Response = Response::new();
     let data = ctx.data.read().await;
-    let tradelists = data.get::<Tradelist::Tradelist>().unwrap();
-    if let Some(mut list) = tradelists.get_mut(&msg.author.id) {
-        list.set_private();
-        digest.set_content( String::from("Your tradelist has been set to private. Your tradelist **will not** be found during tradelist searches." ) );
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let db = data.get::<CardDB::CardDB>().unwrap();
+    let (from, rest) = split_list_name(args.rest());
+    let (to, text) = split_list_name(rest);
+    let (from, to) = match (from, to) {
+        (Some(f), Some(t)) => (f, t),
+        _ => {
+            digest.set_content( String::from("Put the names of both lists in quotes, e.g. '!tradelist move \"Main\" \"Bulk rares\" 4 Lightning Bolt'.") );
+            return digest;
+        }
+    };
+    let (entries, mut errors) = create_entries(db, text);
+
+    let mut moved: Vec<String> = Vec::new();
+    let mut added = Vec::new();
+    let result = store.update(msg.author.id, false, &mut |trader| {
+        added = Notifier::watch(msg.author.id, trader, |trader| {
+            for entry in &entries {
+                match trader.move_cards(from, to, entry, is_want) {
+                    Ok(0) => errors.push(format!("{} is not in \"{}\"", entry.card, from)),
+                    Ok(count) => {
+                        let mut shown = entry.clone();
+                        shown.update_count(count);
+                        moved.push(shown.to_string());
+                    }
+                    Err(why) => {
+                        errors.push(why);
+                        break;
+                    }
+                }
+            }
+        });
+    });
+    match result {
+        Ok(true) => notify(&data, added),
+        Ok(false) => {
+            digest.set_content( String::from(NO_TRADELIST) );
+            return digest;
+        }
+        Err(why) => {
+            store_error(&mut digest, why);
+            return digest;
+        }
+    }
+
+    let mut content = if moved.is_empty() {
+        String::from("Nothing was moved.")
     } else {
-        digest.set_content( String::from( "You do not have a tradelist. To add one, just use the the command '!tradelist add' followed by a quantity and card name." ) );
+        format!("The following cards were moved from \"{}\" to \"{}\":", from.trim(), to.trim())
+    };
+    for m in moved {
+        content += &format!("\n- {}", m);
     }
+    if !errors.is_empty() {
+        content += "\nThe following lines were not moved:";
+        for e in errors {
+            content += &format!("\n- {}", e);
+        }
+    }
+    digest.set_content(content);
     digest
 }
 
 #[command("tradelist")]
-async fn tradelist(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+async fn tradelist(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     let mut digest: Response::Response = Response::new();
-    let mut new_args: Args = Args::new(args.rest(), &[Delimiter::Single(' ')]);
+    // Pasted lists usually start on the line after the task, so newlines also end the task.
+    let mut new_args: Args = Args::new(args.rest(), &[Delimiter::Single(' '), Delimiter::Single('\n')]);
     if let Ok(task) = new_args.single::<String>() {
         println!("Task found: {}", task);
         if task.as_str() == "" {
         } else if task.as_str() == "view" {
-            digest = view_tradelist( ctx, msg ).await;
+            digest = view_tradelist( ctx, msg, new_args ).await;
         } else if task.as_str() == "add" {
-            digest = add_to_tradelist(ctx, msg, new_args).await;
+            digest = add_to_tradelist(ctx, msg, new_args, false).await;
         } else if task.as_str() == "remove" {
-            //"remove" => remove_from_tradelist( ctx, msg, new_args ).await;
+            digest = remove_from_tradelist(ctx, msg, new_args, false).await;
+        } else if task.as_str() == "want" {
+            digest = add_to_tradelist(ctx, msg, new_args, true).await;
+        } else if task.as_str() == "unwant" {
+            digest = remove_from_tradelist(ctx, msg, new_args, true).await;
+        } else if task.as_str() == "import" {
+            digest = import_to_tradelist(ctx, msg, new_args, false).await;
+        } else if task.as_str() == "export" {
+            digest = export_tradelist(ctx, msg, new_args).await;
         } else if task.as_str() == "public" {
-            digest = make_public_tradelist(ctx, msg).await;
+            digest = set_tradelist_visibility(ctx, msg, new_args, true).await;
         } else if task.as_str() == "private" {
-            digest = make_private_tradelist(ctx, msg).await;
+            digest = set_tradelist_visibility(ctx, msg, new_args, false).await;
+        } else if task.as_str() == "lists" {
+            digest = show_lists(ctx, msg).await;
+        } else if matches!(task.as_str(), "create" | "rename" | "delete" | "default") {
+            digest = manage_lists(ctx, msg, task.as_str(), new_args).await;
+        } else if task.as_str() == "move" {
+            digest = move_between_lists(ctx, msg, new_args, false).await;
+        } else if task.as_str() == "movewant" {
+            digest = move_between_lists(ctx, msg, new_args, true).await;
         } else {
             digest.set_content( String::from("You need to specify what you want to do with your tradelist.") );
         }
@@ -455,3 +1165,1540 @@ async fn tradelist(ctx: &Context, msg: &Message, mut args: Args) -> CommandResul
     }
     Response::send_message( digest, ctx, msg ).await
 }
+
+async fn view_collection(ctx: &Context, msg: &Message) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+    match store.get(msg.author.id) {
+        Ok(Some(trader)) => {
+            let collection = trader.collection();
+            let surplus = collection.surplus();
+            let mut content = format!(
+                "Your collection has {} cards, {} of which are surplus.",
+                collection.cards().card_count(), surplus.card_count()
+            );
+            match collection.linked() {
+                Some(list) => content += &format!("\nThe surplus is used as the haves of \"{}\".", list),
+                None => content += "\nThe surplus isn't used by any list. Use '!collection link' to trade it.",
+            }
+            if collection.rules().is_empty() {
+                content += "\nYou don't have any keep rules, so everything is surplus. Add one with e.g. '!collection keep 4'.";
+            } else {
+                content += "\nKeep rules:";
+                for (i, rule) in collection.rules().iter().enumerate() {
+                    content += &format!("\n{}. {}", i + 1, rule);
+                }
+            }
+            digest.set_content(content);
+        }
+        Ok(None) => digest.set_content( String::from("You haven't recorded a collection. Use '!collection add' or '!collection import' to start one.") ),
+        Err(why) => store_error(&mut digest, why),
+    }
+    digest
+}
+
+async fn view_surplus(ctx: &Context, msg: &Message, args: Args) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let db = data.get::<CardDB::CardDB>().unwrap();
+    let options = match ListView::parse_options(args.rest().split_whitespace()) {
+        Ok(o) => o,
+        Err(why) => {
+            digest.set_content(why);
+            return digest;
+        }
+    };
+    match store.get(msg.author.id) {
+        Ok(Some(trader)) => {
+            let surplus = trader.collection().surplus();
+            if surplus.is_empty() {
+                digest.set_content( String::from("Your keep rules keep your whole collection, so there is no surplus.") );
+            } else {
+                let groups = ListView::lines(db, &surplus, "Cards", &options);
+                digest.set_pages( Pager::paginate("Your Surplus", Colour::BLUE, None, &groups, options.page_size) );
+            }
+        }
+        Ok(None) => digest.set_content( String::from("You haven't recorded a collection. Use '!collection add' or '!collection import' to start one.") ),
+        Err(why) => store_error(&mut digest, why),
+    }
+    digest
+}
+
+// Adds cards to or removes cards from the user's collection. The linked list is updated
+// along with it.
+async fn change_collection(ctx: &Context, msg: &Message, args: Args, is_add: bool) -> Response::Response {
+    println!("Changing the collection.");
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let db = data.get::<CardDB::CardDB>().unwrap();
+    let (entries, mut errors) = create_entries(db, args.rest());
+
+    let mut changed: Vec<String> = Vec::new();
+    let mut added = Vec::new();
+    let mut lost = Vec::new();
+    let result = store.update(msg.author.id, is_add, &mut |trader| {
+        added = Notifier::watch(msg.author.id, trader, |trader| trader.update_collection(|collection| {
+            for entry in &entries {
+                if is_add {
+                    if entry.card.pattern.is_some() {
+                        errors.push(format!("{} is a printing pattern, which can only be used for wants", entry.card));
+                        continue;
+                    }
+                    collection.add(entry.clone());
+                    changed.push(entry.to_string());
+                    continue;
+                }
+                let count = collection.remove(entry);
+                let mut shown = entry.clone();
+                shown.update_count(count);
+                if count == 0 {
+                    errors.push(format!("{} is not in your collection", entry.card));
+                } else {
+                    changed.push(shown.to_string());
+                }
+            }
+        }));
+        lost = trader.take_lost_holds();
+    });
+    match result {
+        Ok(true) => notify(&data, added),
+        Ok(false) => {
+            digest.set_content( String::from("You haven't recorded a collection. Use '!collection add' or '!collection import' to start one.") );
+            return digest;
+        }
+        Err(why) => {
+            store_error(&mut digest, why);
+            return digest;
+        }
+    }
+
+    let verb = if is_add { "added to" } else { "removed from" };
+    let mut content = if changed.is_empty() {
+        format!("Nothing was {} your collection.", verb)
+    } else {
+        format!("The following cards were {} your collection:", verb)
+    };
+    for c in changed {
+        content += &format!("\n- {}", c);
+    }
+    if !errors.is_empty() {
+        content += "\nThe following lines were skipped:";
+        for e in errors {
+            content += &format!("\n- {}", e);
+        }
+    }
+    content += &lost_holds_warning(store.as_ref(), msg.author.id, &lost);
+    digest.set_content(content);
+    digest
+}
+
+// Changes the keep rules or which list gets the surplus, e.g. "!collection keep 1 for Commander",
+// "!collection unkeep 2" or '!collection link "Bulk rares"'.
+async fn manage_collection(ctx: &Context, msg: &Message, task: &str, args: Args) -> Response::Response {
+    println!("Managing the collection: {}", task);
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let db = data.get::<CardDB::CardDB>().unwrap();
+    let text = args.rest();
+
+    // Rules are read before taking the store so that a typo doesn't create a trader
+    let rule = if task == "keep" {
+        match Collection::parse_rule(db, text) {
+            Ok(r) => Some(r),
+            Err(why) => {
+                digest.set_content( format!("That rule could not be read: {}. Try e.g. '!collection keep 4', '!collection keep 1 for Commander' or '!collection keep 8 Lightning Bolt'.", why) );
+                return digest;
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut outcome = Err(String::new());
+    let mut added = Vec::new();
+    let mut lost = Vec::new();
+    let result = store.update(msg.author.id, task == "keep" || task == "link", &mut |trader| {
+        // Dropping a rule or linking a list can put new cards up for trade
+        added = Notifier::watch(msg.author.id, trader, |trader| outcome = match task {
+            "keep" => {
+                let rule = rule.clone().unwrap();
+                let shown = rule.to_string();
+                trader.update_collection(|c| c.add_rule(rule));
+                Ok(format!("Your collection will now {}.", shown))
+            }
+            "unkeep" => match text.trim().parse::<usize>() {
+                Ok(number) => {
+                    let mut removed = Err(String::new());
+                    trader.update_collection(|c| removed = c.remove_rule(number));
+                    removed.map(|r| format!("You will no longer {}.", r))
+                }
+                Err(_) => Err(String::from("You need to give the number of the rule to remove. Use '!collection view' to see them.")),
+            },
+            "link" => trader
+                .link_collection(list_arg(text))
+                .map(|name| format!("The haves of \"{}\" will now be the surplus of your collection.", name)),
+            _ => match trader.unlink_collection() {
+                Some(name) => Ok(format!("\"{}\" is no longer linked to your collection. Its haves have been left as they are.", name)),
+                None => Err(String::from("None of your lists are linked to your collection.")),
+            },
+        });
+        lost = trader.take_lost_holds();
+    });
+    if let Ok(true) = result {
+        notify(&data, added);
+    }
+    match (result, outcome) {
+        (Err(why), _) => store_error(&mut digest, why),
+        (Ok(false), _) => digest.set_content( String::from("You haven't recorded a collection. Use '!collection add' or '!collection import' to start one.") ),
+        (Ok(true), Err(why)) => digest.set_content(why),
+        (Ok(true), Ok(content)) => digest.set_content( content + &lost_holds_warning(store.as_ref(), msg.author.id, &lost) ),
+    }
+    digest
+}
+
+// Records the user's whole collection. Keep rules decide how many copies of each card are
+// kept, and everything above them is surplus that a linked list offers for trade.
+#[command("collection")]
+async fn collection(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut digest: Response::Response = Response::new();
+    let mut new_args: Args = Args::new(args.rest(), &[Delimiter::Single(' '), Delimiter::Single('\n')]);
+    if let Ok(task) = new_args.single::<String>() {
+        println!("Task found: {}", task);
+        if task.as_str() == "view" {
+            digest = view_collection(ctx, msg).await;
+        } else if task.as_str() == "surplus" {
+            digest = view_surplus(ctx, msg, new_args).await;
+        } else if task.as_str() == "add" {
+            digest = change_collection(ctx, msg, new_args, true).await;
+        } else if task.as_str() == "remove" {
+            digest = change_collection(ctx, msg, new_args, false).await;
+        } else if task.as_str() == "import" {
+            digest = import_to_tradelist(ctx, msg, new_args, true).await;
+        } else if matches!(task.as_str(), "keep" | "unkeep" | "link" | "unlink") {
+            digest = manage_collection(ctx, msg, task.as_str(), new_args).await;
+        } else {
+            digest.set_content( String::from("You need to specify what you want to do with your collection.") );
+        }
+    } else {
+        digest.set_content( String::from("You need to specify what you want to do with your collection.") );
+    }
+    Response::send_message( digest, ctx, msg ).await
+}
+
+// Compares the user's lists with everyone else's public lists. By default all of the user's
+// lists are used; naming lists in quotes narrows it down, e.g. '!matches "Commander wants"'.
+// Each trader's reputation is shown, and 'rep:N' leaves out traders with a lower score.
+// 'scope:store', 'scope:city' or 'scope:region' only looks at traders the user can meet, and
+// 'scope:ship' at anyone they can trade with. 'size:30' shows 30 matches per page.
+#[command("matches")]
+async fn matches(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let db = data.get::<CardDB::CardDB>().unwrap();
+
+    let mut selected: Vec<&str> = Vec::new();
+    let mut rest = args.rest();
+    while let (Some(name), remaining) = split_list_name(rest) {
+        selected.push(name);
+        rest = remaining;
+    }
+    let mut minimum_rep = None;
+    let mut scope = None;
+    let mut page_size = Pager::DEFAULT_PAGE_SIZE;
+    let mut unquoted: Vec<&str> = Vec::new();
+    for token in rest.split_whitespace() {
+        if let Some(m) = Reputation::parse_minimum(token) {
+            minimum_rep = Some(m);
+        } else if let Some(size) = Pager::parse_page_size(token) {
+            page_size = size;
+        } else if let Some(s) = Profile::parse_scope(token) {
+            match s {
+                Ok(s) => scope = Some(s),
+                Err(why) => {
+                    digest.set_content(why);
+                    return Response::send_message( digest, ctx, msg ).await;
+                }
+            }
+        } else {
+            unquoted.push(token);
+        }
+    }
+    let unquoted = unquoted.join(" ");
+    if selected.is_empty() {
+        selected.extend(list_arg(&unquoted));
+    }
+
+    let lists = store
+        .get(msg.author.id)
+        .and_then(|mine| Ok((mine, store.public()?, store.proposals()?)));
+    let (mine, others, proposals) = match lists {
+        Ok(l) => l,
+        Err(why) => {
+            store_error(&mut digest, why);
+            return Response::send_message( digest, ctx, msg ).await;
+        }
+    };
+    let mine = match mine {
+        Some(m) => m,
+        None => {
+            digest.set_content( String::from(NO_TRADELIST) );
+            return Response::send_message( digest, ctx, msg ).await;
+        }
+    };
+    let mut my_lists = Vec::new();
+    for name in &selected {
+        match mine.resolve(Some(name)) {
+            Ok(n) => my_lists.push(mine.get(Some(&n)).unwrap()),
+            Err(why) => {
+                digest.set_content(why);
+                return Response::send_message( digest, ctx, msg ).await;
+            }
+        }
+    }
+    if selected.is_empty() {
+        my_lists.extend(mine.lists().map(|(_, l)| l));
+    }
+    let default = match guild_scope(&data, msg.guild_id) {
+        Ok(d) => d,
+        Err(why) => {
+            store_error(&mut digest, why);
+            return Response::send_message( digest, ctx, msg ).await;
+        }
+    };
+    let scope = match search_scope(scope, default, mine.profile()) {
+        Ok(s) => s,
+        Err(why) => {
+            digest.set_content(why);
+            return Response::send_message( digest, ctx, msg ).await;
+        }
+    };
+
+    let profiles = match partner_profiles(store.as_ref(), others.iter().map(|(id, _, _)| *id)) {
+        Ok(p) => p,
+        Err(why) => {
+            store_error(&mut digest, why);
+            return Response::send_message( digest, ctx, msg ).await;
+        }
+    };
+    let reputations = Reputation::all(&proposals);
+    // Partners that are easier to trade with come first
+    let mut has: Vec<(u8, String)> = Vec::new();
+    let mut wants: Vec<(u8, String)> = Vec::new();
+    for (id, name, other) in others.iter().filter(|(id, _, _)| *id != msg.author.id) {
+        let reputation = reputations.get(id).copied().unwrap_or_default();
+        if minimum_rep.map_or(false, |m| reputation.score() < m) {
+            continue;
+        }
+        let profile = &profiles[id];
+        if !mine.profile().within(profile, scope) {
+            continue;
+        }
+        let closeness = mine.profile().closeness(profile);
+        let partner = describe_partner(*id, Some(reputation), profile);
+        let (their_haves, their_wants) = match_lines(db, &my_lists, &partner, name, other);
+        has.extend(their_haves.into_iter().map(|line| (closeness, line)));
+        wants.extend(their_wants.into_iter().map(|line| (closeness, line)));
+    }
+    if has.is_empty() && wants.is_empty() {
+        digest.set_content( format!("No one with a public tradelist{} has what you want or wants what you have.", describe_scope(scope)) );
+    } else {
+        let by_closeness = |mut lines: Vec<(u8, String)>| -> Vec<String> {
+            lines.sort_by_key(|(closeness, _)| std::cmp::Reverse(*closeness));
+            lines.into_iter().map(|(_, line)| line).collect()
+        };
+        let intro = format!("Here are your matches{}:", describe_scope(scope));
+        digest.set_pages( match_pages("Your Matches", &intro, by_closeness(has), by_closeness(wants), page_size) );
+    }
+    Response::send_message( digest, ctx, msg ).await
+}
+
+fn default_scope() -> Profile::Scope {
+    match env::var("TAVERN_SCOPE") {
+        Ok(scope) => scope.parse().unwrap_or_else(|_| {
+            println!("TAVERN_SCOPE '{}' isn't a scope (store, city, region or ship), so the default scope is ship", scope);
+            Profile::Scope::Ship
+        }),
+        Err(_) => Profile::Scope::Ship,
+    }
+}
+
+// The default scope where a message was sent: the guild's own if it chose one, otherwise
+// the bot's.
+fn guild_scope(data: &TypeMap, guild: Option<GuildId>) -> TradeStore::StoreResult<Profile::Scope> {
+    let own = match guild {
+        Some(g) => data.get::<TradeStore::Store>().unwrap().settings(g)?.scope,
+        None => None,
+    };
+    Ok(own.unwrap_or(*data.get::<DefaultScope>().unwrap()))
+}
+
+// The scope to search within. A scope that was asked for needs the caller's profile to say
+// where they are. The default one just falls back to shipping when it doesn't.
+fn search_scope(requested: Option<Profile::Scope>, default: Profile::Scope, mine: &Profile::Profile) -> Result<Profile::Scope, String> {
+    match requested {
+        Some(scope) if !mine.has_scope(scope) => Err(format!(
+            "Your profile doesn't say which {} you're in. Add it with '!profile set {} ...' first.",
+            scope, scope
+        )),
+        Some(scope) => Ok(scope),
+        None if mine.has_scope(default) => Ok(default),
+        None => Ok(Profile::Scope::Ship),
+    }
+}
+
+// The profiles of the given users. Users without one get an empty profile, which neither
+// rules them out nor ranks them.
+fn partner_profiles(store: &dyn TradeStore::TradeStore, users: impl Iterator<Item = UserId>) -> TradeStore::StoreResult<HashMap<UserId, Profile::Profile>> {
+    let mut digest = HashMap::new();
+    for user in users {
+        if let std::collections::hash_map::Entry::Vacant(entry) = digest.entry(user) {
+            entry.insert(store.profile(user)?.unwrap_or_default());
+        }
+    }
+    Ok(digest)
+}
+
+// The matches between the user's lists and one of the partner's lists, one per line: what
+// the partner has that the user wants, and what the partner wants that the user has.
+fn match_lines(db: &CardDB::CardDB, my_lists: &[&Tradelist::Tradelist], partner: &str, name: &str, other: &Tradelist::Tradelist) -> (Vec<String>, Vec<String>) {
+    let mut has = Vec::new();
+    let mut wants = Vec::new();
+    for list in my_lists {
+        for want in list.wants().iter() {
+            let mut haves: Vec<&CardEntry::CardEntry> = other.haves().matching(want).collect();
+            want.card.sort_by_preference(db, &mut haves);
+            for have in haves {
+                has.push(format!("{} has {} in \"{}\" (you want {})", partner, have, name, want));
+            }
+        }
+        for want in other.wants().iter() {
+            for have in list.haves().matching(want) {
+                wants.push(format!("{} wants {} in \"{}\" (you have {})", partner, want, name, have));
+            }
+        }
+    }
+    (has, wants)
+}
+
+// Splits the matches into pages, with what others have and what they want in their own groups.
+fn match_pages(title: &str, intro: &str, has: Vec<String>, wants: Vec<String>, page_size: usize) -> Vec<Response::EmbedSpoof> {
+    let groups = vec![
+        (String::from("They have what you want"), has),
+        (String::from("They want what you have"), wants),
+    ];
+    Pager::paginate(title, Colour::GOLD, Some(intro), &groups, page_size)
+}
+
+// How search results describe the scope they were limited to.
+fn describe_scope(scope: Profile::Scope) -> &'static str {
+    match scope {
+        Profile::Scope::Store => " at your store",
+        Profile::Scope::City => " in your city",
+        Profile::Scope::Region => " in your region",
+        Profile::Scope::Ship => "",
+    }
+}
+
+// A partner in search results, like "<@id> (+3, Portland, US, ships)".
+fn describe_partner(user: UserId, reputation: Option<Reputation::Reputation>, profile: &Profile::Profile) -> String {
+    let mut details = Vec::new();
+    if let Some(reputation) = reputation {
+        details.push(if reputation.total() == 0 { String::from("new") } else { format!("{:+}", reputation.score()) });
+    }
+    let summary = profile.summary();
+    if !summary.is_empty() {
+        details.push(summary);
+    }
+    if details.is_empty() {
+        format!("<@{}>", user)
+    } else {
+        format!("<@{}> ({})", user, details.join(", "))
+    }
+}
+
+// Searches public tradelists for a card. Details after the name narrow the search, e.g.
+// "!whohas Black Lotus [LEA] PSA 8" or "!whohas Island signed". Like matches, it takes a
+// scope such as 'scope:city'.
+#[command("whohas")]
+async fn whohas(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let db = data.get::<CardDB::CardDB>().unwrap();
+
+    let mut scope = None;
+    let mut search: Vec<&str> = Vec::new();
+    for token in args.rest().split_whitespace() {
+        match Profile::parse_scope(token) {
+            Some(Ok(s)) => scope = Some(s),
+            Some(Err(why)) => {
+                digest.set_content(why);
+                return Response::send_message( digest, ctx, msg ).await;
+            }
+            None => search.push(token),
+        }
+    }
+
+    match Decklist::parse_card_details(db, &search.join(" "), 1) {
+        Err(why) => digest.set_content( format!("Your search could not be read: {}.", why) ),
+        Ok(query) => {
+            let found = store.who_has(&query.card.get_name()).and_then(|haves| {
+                let profiles = partner_profiles(store.as_ref(), haves.iter().map(|(id, _, _)| *id).chain([msg.author.id]))?;
+                Ok((haves, profiles, guild_scope(&data, msg.guild_id)?))
+            });
+            let (haves, profiles, default) = match found {
+                Ok(f) => f,
+                Err(why) => {
+                    store_error(&mut digest, why);
+                    return Response::send_message( digest, ctx, msg ).await;
+                }
+            };
+            let mine = &profiles[&msg.author.id];
+            let scope = match search_scope(scope, default, mine) {
+                Ok(s) => s,
+                Err(why) => {
+                    digest.set_content(why);
+                    return Response::send_message( digest, ctx, msg ).await;
+                }
+            };
+            let mut haves: Vec<_> = haves
+                .iter()
+                .filter(|(id, _, c)| *id != msg.author.id && c.available() > 0 && c.fits_search(&query) && mine.within(&profiles[id], scope))
+                .collect();
+            haves.sort_by_key(|(id, _, _)| std::cmp::Reverse(mine.closeness(&profiles[id])));
+            let mut content = String::new();
+            for (id, list, have) in haves {
+                content += &format!("\n{} has {} in \"{}\"", describe_partner(*id, None, &profiles[id]), have, list);
+            }
+            if content.is_empty() {
+                digest.set_content( format!("No one with a public tradelist{} has that card.", describe_scope(scope)) );
+            } else {
+                digest.set_content( format!("Here is who has {}{}:{}", query.card, describe_scope(scope), content) );
+            }
+        }
+    }
+    Response::send_message( digest, ctx, msg ).await
+}
+
+// Compares the user's lists with another user's public lists, e.g. "!compare @someone size:30".
+// Shows what the other user has that the caller wants, what the caller has that they want
+// and the cards that both of them have.
+#[command("compare")]
+async fn compare(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let db = data.get::<CardDB::CardDB>().unwrap();
+
+    let mut tokens = args.rest().split_whitespace();
+    let other = match tokens.next().and_then(serenity::utils::parse_username) {
+        Some(id) => UserId(id),
+        None => {
+            digest.set_content( String::from("Mention the user you want to compare tradelists with, e.g. '!compare @someone'.") );
+            return Response::send_message( digest, ctx, msg ).await;
+        }
+    };
+    if other == msg.author.id {
+        digest.set_content( String::from("You can only compare your tradelists with someone else's.") );
+        return Response::send_message( digest, ctx, msg ).await;
+    }
+    let page_size = tokens.find_map(Pager::parse_page_size).unwrap_or(Pager::DEFAULT_PAGE_SIZE);
+
+    let traders = store
+        .get(msg.author.id)
+        .and_then(|mine| Ok((mine, store.get(other)?)));
+    let (mine, theirs) = match traders {
+        Ok((Some(mine), theirs)) => (mine, theirs),
+        Ok((None, _)) => {
+            digest.set_content( String::from(NO_TRADELIST) );
+            return Response::send_message( digest, ctx, msg ).await;
+        }
+        Err(why) => {
+            store_error(&mut digest, why);
+            return Response::send_message( digest, ctx, msg ).await;
+        }
+    };
+    let their_lists: Vec<(&String, &Tradelist::Tradelist)> = theirs
+        .as_ref()
+        .map(|t| t.lists().filter(|(_, l)| l.is_public()).collect())
+        .unwrap_or_default();
+    if their_lists.is_empty() {
+        digest.set_content( format!("<@{}> doesn't have a public tradelist.", other) );
+        return Response::send_message( digest, ctx, msg ).await;
+    }
+
+    let mut they_have: Vec<String> = Vec::new();
+    let mut you_have: Vec<String> = Vec::new();
+    let mut both_have: Vec<String> = Vec::new();
+    for (my_name, my_list) in mine.lists() {
+        for (their_name, their_list) in &their_lists {
+            for want in my_list.wants().iter() {
+                let mut haves: Vec<&CardEntry::CardEntry> = their_list.haves().matching(want).collect();
+                want.card.sort_by_preference(db, &mut haves);
+                for have in haves {
+                    they_have.push(format!("{} in \"{}\" (you want {})", have, their_name, want));
+                }
+            }
+            for want in their_list.wants().iter() {
+                for have in my_list.haves().matching(want) {
+                    you_have.push(format!("{} in \"{}\" (they want {})", have, my_name, want));
+                }
+            }
+            for have in my_list.haves().iter().filter(|h| their_list.contains_card(h.card.clone())) {
+                both_have.push(format!("{} (your \"{}\" and their \"{}\")", have, my_name, their_name));
+            }
+        }
+    }
+
+    let name = match other.to_user(ctx).await {
+        Ok(user) => user.name,
+        Err(_) => String::from("another user"),
+    };
+    let groups = vec![
+        (String::from("They have what you want"), they_have),
+        (String::from("You have what they want"), you_have),
+        (String::from("You both have"), both_have),
+    ];
+    digest.set_pages( Pager::paginate(&format!("Your tradelists compared with {}'s", name), Colour::GOLD, None, &groups, page_size) );
+    Response::send_message( digest, ctx, msg ).await
+}
+
+// The cards given, the cards asked for and the declared value of what is given.
+type Offer = (Vec<CardEntry::CardEntry>, Vec<CardEntry::CardEntry>, Option<f64>);
+
+// Reads both sides of an offer, e.g. "give: 2 Lightning Bolt; 1 Counterspell get: 4 Brainstorm value: 10".
+fn parse_offer(db: &CardDB::CardDB, text: &str) -> Result<Offer, String> {
+    let (text, value) = Proposal::split_value(text)?;
+    let (give, get) = Proposal::split_offer(&text)?;
+    let (give, mut errors) = create_entries(db, &give);
+    let (get, get_errors) = create_entries(db, &get);
+    errors.extend(get_errors);
+    if !errors.is_empty() {
+        return Err(format!("Your offer could not be read:\n{}", errors.join("\n")));
+    }
+    if give.is_empty() && get.is_empty() {
+        return Err(String::from("An offer needs at least one card."));
+    }
+    Ok((give, get, value))
+}
+
+// Reads a proposal id like "12" or "#12".
+fn proposal_id(args: &Args) -> Result<u64, String> {
+    let text = args.rest().split_whitespace().next().unwrap_or("");
+    text.trim_start_matches('#')
+        .parse::<u64>()
+        .map_err(|_| String::from("Give the number of the proposal, e.g. '!trade accept 12'."))
+}
+
+// Holds the cards that the user offers in a new proposal. If they can't be held, says why
+// and returns false.
+fn hold_offer(store: &dyn TradeStore::TradeStore, digest: &mut Response::Response, user: UserId, cards: &[CardEntry::CardEntry]) -> bool {
+    if cards.is_empty() {
+        return true;
+    }
+    let mut outcome = Ok(());
+    match store.update(user, false, &mut |trader| outcome = trader.reserve_cards(cards)) {
+        Ok(true) => match outcome {
+            Ok(()) => true,
+            Err(missing) => {
+                digest.set_content( format!("You can only offer cards from your haves that aren't held by another proposal. You're missing {}.", missing) );
+                false
+            }
+        },
+        Ok(false) => {
+            digest.set_content( String::from(NO_TRADELIST) );
+            false
+        }
+        Err(why) => {
+            store_error(digest, why);
+            false
+        }
+    }
+}
+
+// Lets go of the cards held for a proposal that was never made.
+fn release_offer(store: &dyn TradeStore::TradeStore, user: UserId, cards: &[CardEntry::CardEntry]) {
+    if let Err(why) = store.update(user, false, &mut |trader| trader.release_cards(cards)) {
+        println!("Trade store error: {}", why);
+    }
+}
+
+// Lets go of the cards held for a proposal that was in the given status. Open proposals
+// only hold the proposer's cards, and accepted ones hold both sides.
+fn release_holds(proposal: &Proposal::Proposal, was: Proposal::Status, from: &mut Trader::Trader, to: &mut Trader::Trader) {
+    from.release_cards(&proposal.give);
+    if was == Proposal::Status::Accepted {
+        to.release_cards(&proposal.get);
+    }
+}
+
+// Expires the proposal if it is past its expiry, letting go of the cards that it held.
+// Returns true if it did.
+fn expire_proposal(proposal: &mut Proposal::Proposal, from: &mut Trader::Trader, to: &mut Trader::Trader) -> bool {
+    match proposal.expire() {
+        Some(was) => {
+            release_holds(proposal, was, from, to);
+            true
+        }
+        None => false,
+    }
+}
+
+// Proposes a trade to another user, e.g. '!trade offer @someone give: 2 Lightning Bolt get: 1 Counterspell'.
+// The cards offered are held until the proposal is closed. 'value: 12.50' declares what the
+// cards given are worth, for the trade history.
+async fn offer_trade(ctx: &Context, msg: &Message, args: Args) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let db = data.get::<CardDB::CardDB>().unwrap();
+
+    let text = args.rest().trim_start();
+    let (mention, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let other = match serenity::utils::parse_username(mention) {
+        Some(id) => UserId(id),
+        None => {
+            digest.set_content( String::from("Mention who the offer is for, e.g. '!trade offer @someone give: 2 Lightning Bolt get: 1 Counterspell'.") );
+            return digest;
+        }
+    };
+    if other == msg.author.id {
+        digest.set_content( String::from("You can't trade with yourself.") );
+        return digest;
+    }
+    let (give, get, value) = match parse_offer(db, rest) {
+        Ok(o) => o,
+        Err(why) => {
+            digest.set_content(why);
+            return digest;
+        }
+    };
+    if !hold_offer(store.as_ref(), &mut digest, msg.author.id, &give) {
+        return digest;
+    }
+    let mut proposal = Proposal::new(msg.author.id, other, give.clone(), get, None);
+    if let Some(v) = value {
+        proposal.declare(msg.author.id, v);
+    }
+    match store.propose(proposal) {
+        Ok(id) => {
+            let proposal = store.proposal(id).ok().flatten();
+            let summary = proposal.map(|p| p.to_string()).unwrap_or_default();
+            digest.set_content( format!("<@{}>, you have a new trade proposal:\n{}\nUse '!trade accept {}', '!trade decline {}' or '!trade counter {} give: ... get: ...' to respond.", other, summary, id, id, id) );
+        }
+        Err(why) => {
+            release_offer(store.as_ref(), msg.author.id, &give);
+            store_error(&mut digest, why);
+        }
+    }
+    digest
+}
+
+// Closes an open proposal as declined or withdrawn, letting go of the cards that it held.
+async fn close_trade(ctx: &Context, msg: &Message, args: Args, status: Proposal::Status) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+
+    let id = match proposal_id(&args) {
+        Ok(id) => id,
+        Err(why) => {
+            digest.set_content(why);
+            return digest;
+        }
+    };
+    let mut outcome: Result<Proposal::Proposal, String> = Err(format!("There is no proposal #{}.", id));
+    let result = store.update_trade(id, &mut |proposal, from, to| {
+        if expire_proposal(proposal, from, to) {
+            outcome = Err(format!("Proposal #{} is expired.", id));
+            return true;
+        }
+        outcome = proposal.close(msg.author.id, status).map(|_| proposal.clone());
+        if outcome.is_ok() {
+            from.release_cards(&proposal.give);
+        }
+        outcome.is_ok()
+    });
+    match result {
+        Ok(_) => match outcome {
+            Ok(proposal) => {
+                let other = if proposal.from == msg.author.id { proposal.to } else { proposal.from };
+                digest.set_content( format!("<@{}>, proposal #{} was {}.", other, id, status) );
+            }
+            Err(why) => digest.set_content(why),
+        },
+        Err(why) => store_error(&mut digest, why),
+    }
+    digest
+}
+
+// Accepts a proposal, e.g. '!trade accept 12 value: 8'. The cards asked for are held as well,
+// so both sides are set aside until the trade is completed. The value, if given, is what
+// the cards asked for are worth to the one accepting.
+async fn accept_trade(ctx: &Context, msg: &Message, args: Args) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+
+    let id = match proposal_id(&args) {
+        Ok(id) => id,
+        Err(why) => {
+            digest.set_content(why);
+            return digest;
+        }
+    };
+    let value = match Proposal::split_value(args.rest()) {
+        Ok((_, v)) => v,
+        Err(why) => {
+            digest.set_content(why);
+            return digest;
+        }
+    };
+    let mut outcome: Result<Proposal::Proposal, String> = Err(format!("There is no proposal #{}.", id));
+    let result = store.update_trade(id, &mut |proposal, from, to| {
+        if expire_proposal(proposal, from, to) {
+            outcome = Err(format!("Proposal #{} is expired.", id));
+            return true;
+        }
+        if let Err(why) = proposal.close(msg.author.id, Proposal::Status::Accepted) {
+            outcome = Err(why);
+            return false;
+        }
+        if let Some(v) = value {
+            proposal.declare(msg.author.id, v);
+        }
+        // The proposer's cards were held when they made the offer, but their collection may
+        // have shrunk since. Holding them again makes sure that they are all still there.
+        from.release_cards(&proposal.give);
+        if let Err(missing) = from.reserve_cards(&proposal.give) {
+            outcome = Err(format!("Proposal #{} can't be accepted, since <@{}> no longer has {}.", id, proposal.from, missing));
+            return false;
+        }
+        match to.reserve_cards(&proposal.get) {
+            Ok(()) => {
+                outcome = Ok(proposal.clone());
+                true
+            }
+            Err(missing) => {
+                outcome = Err(format!("You can't accept proposal #{}, since you're missing {}. Cards held by other proposals can't be traded twice.", id, missing));
+                false
+            }
+        }
+    });
+    match result {
+        Ok(_) => match outcome {
+            Ok(accepted) => digest.set_content( format!("<@{}>, proposal #{} was accepted:\n{}\nOnce the cards have changed hands, both of you use '!trade complete {}' to update your lists, or '!trade cancel {}' to call it off.", accepted.from, id, accepted, id, id) ),
+            Err(why) => digest.set_content(why),
+        },
+        Err(why) => store_error(&mut digest, why),
+    }
+    digest
+}
+
+// Answers a proposal with a new one going the other way, e.g. '!trade counter 12 give: 1 Counterspell get: 3 Lightning Bolt'.
+async fn counter_trade(ctx: &Context, msg: &Message, args: Args) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let db = data.get::<CardDB::CardDB>().unwrap();
+
+    let id = match proposal_id(&args) {
+        Ok(id) => id,
+        Err(why) => {
+            digest.set_content(why);
+            return digest;
+        }
+    };
+    let text = args.rest().trim_start();
+    let rest = text.split_once(char::is_whitespace).map_or("", |(_, r)| r);
+    let (give, get, value) = match parse_offer(db, rest) {
+        Ok(o) => o,
+        Err(why) => {
+            digest.set_content(why);
+            return digest;
+        }
+    };
+    // Checks that the proposal can be countered before making the counter
+    let original = match store.proposal(id) {
+        Ok(Some(p)) => p,
+        Ok(None) => {
+            digest.set_content( format!("There is no proposal #{}.", id) );
+            return digest;
+        }
+        Err(why) => {
+            store_error(&mut digest, why);
+            return digest;
+        }
+    };
+    if let Err(why) = original.clone().close(msg.author.id, Proposal::Status::Countered(0)) {
+        digest.set_content(why);
+        return digest;
+    }
+    if !hold_offer(store.as_ref(), &mut digest, msg.author.id, &give) {
+        return digest;
+    }
+    let mut proposal = Proposal::new(msg.author.id, original.from, give.clone(), get, Some(id));
+    if let Some(v) = value {
+        proposal.declare(msg.author.id, v);
+    }
+    let counter = match store.propose(proposal) {
+        Ok(c) => c,
+        Err(why) => {
+            release_offer(store.as_ref(), msg.author.id, &give);
+            store_error(&mut digest, why);
+            return digest;
+        }
+    };
+    let mut outcome = Ok(());
+    let result = store.update_trade(id, &mut |proposal, from, to| {
+        if expire_proposal(proposal, from, to) {
+            outcome = Err(format!("Proposal #{} is expired.", id));
+            return true;
+        }
+        outcome = proposal.close(msg.author.id, Proposal::Status::Countered(counter));
+        if outcome.is_ok() {
+            from.release_cards(&proposal.give);
+        }
+        outcome.is_ok()
+    });
+    if result.is_err() || outcome.is_err() {
+        // The original changed in the meantime, so the counter is taken back
+        let _ = store.update_trade(counter, &mut |proposal, from, _| {
+            let withdrawn = proposal.close(msg.author.id, Proposal::Status::Withdrawn).is_ok();
+            if withdrawn {
+                from.release_cards(&proposal.give);
+            }
+            withdrawn
+        });
+        match (result, outcome) {
+            (Err(why), _) => store_error(&mut digest, why),
+            (_, Err(why)) => digest.set_content(why),
+            _ => {}
+        }
+        return digest;
+    }
+    match store.proposal(counter) {
+        Ok(Some(proposal)) => digest.set_content( format!("<@{}>, proposal #{} was countered with:\n{}\nUse '!trade accept {}', '!trade decline {}' or '!trade counter {} give: ... get: ...' to respond.", original.from, id, proposal, counter, counter, counter) ),
+        Ok(None) => digest.set_content( format!("Proposal #{} was countered with #{}.", id, counter) ),
+        Err(why) => store_error(&mut digest, why),
+    }
+    digest
+}
+
+// Calls off an accepted trade that was never completed, e.g. '!trade cancel 12'. Both sides'
+// cards are let go.
+async fn cancel_trade(ctx: &Context, msg: &Message, args: Args) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+
+    let id = match proposal_id(&args) {
+        Ok(id) => id,
+        Err(why) => {
+            digest.set_content(why);
+            return digest;
+        }
+    };
+    let mut outcome: Result<Proposal::Proposal, String> = Err(format!("There is no proposal #{}.", id));
+    let result = store.update_trade(id, &mut |proposal, from, to| {
+        if expire_proposal(proposal, from, to) {
+            outcome = Err(format!("Proposal #{} is expired.", id));
+            return true;
+        }
+        outcome = proposal.cancel(msg.author.id).map(|_| proposal.clone());
+        if outcome.is_ok() {
+            release_holds(proposal, Proposal::Status::Accepted, from, to);
+        }
+        outcome.is_ok()
+    });
+    match result {
+        Ok(_) => match outcome {
+            Ok(proposal) => {
+                let other = if proposal.from == msg.author.id { proposal.to } else { proposal.from };
+                digest.set_content( format!("<@{}>, trade #{} was cancelled. The cards held for it are free again.", other, id) );
+            }
+            Err(why) => digest.set_content(why),
+        },
+        Err(why) => store_error(&mut digest, why),
+    }
+    digest
+}
+
+// Lets go of the cards held by open and accepted proposals that have expired. Run periodically.
+fn expire_proposals(store: &dyn TradeStore::TradeStore) -> TradeStore::StoreResult<()> {
+    for proposal in store.proposals()?.iter().filter(|p| p.needs_expiring()) {
+        store.update_trade(proposal.id, &mut |proposal, from, to| expire_proposal(proposal, from, to))?;
+    }
+    Ok(())
+}
+
+// Confirms that an accepted trade happened, e.g. '!trade complete 12' or '!trade complete 12 collection'
+// to also add the cards received to the user's collection. Once both users confirm, the
+// cards are taken out of each giver's haves and the wants they fill are removed, all at once.
+async fn complete_trade(ctx: &Context, msg: &Message, args: Args) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let db = data.get::<CardDB::CardDB>().unwrap();
+
+    let id = match proposal_id(&args) {
+        Ok(id) => id,
+        Err(why) => {
+            digest.set_content(why);
+            return digest;
+        }
+    };
+    let collect = args.rest().split_whitespace().skip(1).any(|t| t.eq_ignore_ascii_case("collection"));
+    let user = msg.author.id;
+    let mut outcome: Result<String, String> = Err(format!("There is no proposal #{}.", id));
+    let mut added = Vec::new();
+    let mut lost = Vec::new();
+    let result = store.update_trade(id, &mut |proposal, from, to| {
+        match proposal.confirm(user, collect) {
+            Err(why) => {
+                outcome = Err(why);
+                false
+            }
+            Ok(false) => {
+                let other = if user == proposal.from { proposal.to } else { proposal.from };
+                outcome = Ok(format!("<@{}>, <@{}> confirmed that trade #{} happened. Use '!trade complete {}' once you have your cards too.", other, user, id, id));
+                true
+            }
+            Ok(true) => {
+                // The held cards are the ones that change hands
+                from.release_cards(&proposal.give);
+                to.release_cards(&proposal.get);
+                let given = from
+                    .give_cards(&proposal.give)
+                    .map_err(|why| format!("<@{}> is {}", proposal.from, why))
+                    .and_then(|_| to.give_cards(&proposal.get).map_err(|why| format!("<@{}> is {}", proposal.to, why)));
+                if let Err(why) = given {
+                    outcome = Err(format!("Trade #{} can't be completed, since {}. Update your lists and confirm again.", id, why));
+                    return false;
+                }
+                let mut from_filled = Vec::new();
+                let mut to_filled = Vec::new();
+                // Cards received into a collection can show up in a linked list's haves
+                added = Notifier::watch(proposal.from, from, |t| from_filled = t.receive_cards(&proposal.get, proposal.collects(proposal.from)));
+                added.extend(Notifier::watch(proposal.to, to, |t| to_filled = t.receive_cards(&proposal.give, proposal.collects(proposal.to))));
+                lost = vec![(proposal.from, from.take_lost_holds()), (proposal.to, to.take_lost_holds())];
+                proposal.complete(db);
+                outcome = Ok(proposal.receipt(&from_filled, &to_filled));
+                true
+            }
+        }
+    });
+    if let Ok(true) = result {
+        notify(&data, added);
+    }
+    match result {
+        Ok(_) => match outcome {
+            Ok(mut content) => {
+                for (trader, cards) in lost {
+                    content += &lost_holds_warning(store.as_ref(), trader, &cards);
+                }
+                digest.set_content(content)
+            }
+            Err(why) => digest.set_content(why),
+        },
+        Err(why) => store_error(&mut digest, why),
+    }
+    digest
+}
+
+// Shows a single proposal, or every open proposal that the user is part of.
+async fn view_trades(ctx: &Context, msg: &Message, args: Args) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+
+    if !args.rest().trim().is_empty() {
+        match proposal_id(&args).map(|id| (id, store.proposal(id))) {
+            Ok((_, Ok(Some(p)))) if p.involves(msg.author.id) => {
+                let timing = if p.holds_cards() { format!("expires <t:{}:R>", p.expires) } else { format!("made <t:{}:R>", p.created) };
+                let counters = p.counters.map(|c| format!(", countering #{}", c)).unwrap_or_default();
+                digest.set_content( format!("{}\nThis proposal {}{}.", p, timing, counters) );
+            }
+            Ok((id, Ok(_))) => digest.set_content( format!("There is no proposal #{} for you.", id) ),
+            Ok((_, Err(why))) => store_error(&mut digest, why),
+            Err(why) => digest.set_content(why),
+        }
+        return digest;
+    }
+
+    let proposals = match store.proposals_for(msg.author.id) {
+        Ok(p) => p,
+        Err(why) => {
+            store_error(&mut digest, why);
+            return digest;
+        }
+    };
+    let accepted: Vec<&Proposal::Proposal> = proposals.iter().filter(|p| p.status() == Proposal::Status::Accepted).collect();
+    let (to_you, from_you): (Vec<&Proposal::Proposal>, Vec<&Proposal::Proposal>) = proposals
+        .iter()
+        .filter(|p| p.is_open())
+        .partition(|p| p.to == msg.author.id);
+    if to_you.is_empty() && from_you.is_empty() && accepted.is_empty() {
+        digest.set_content( String::from("You don't have any open proposals. Make one with '!trade offer @someone give: ... get: ...'.") );
+        return digest;
+    }
+    let groups = vec![
+        (String::from("Waiting on you"), to_you.iter().map(|p| p.to_string()).collect()),
+        (String::from("Waiting on others"), from_you.iter().map(|p| p.to_string()).collect()),
+        (String::from("Waiting to be completed"), accepted.iter().map(|p| p.to_string()).collect()),
+    ];
+    digest.set_pages( Pager::paginate("Your Trade Proposals", Colour::GOLD, None, &groups, Pager::DEFAULT_PAGE_SIZE) );
+    digest
+}
+
+// Shows the trades that a user completed, newest first, e.g. '!trades history @someone'. With
+// to_csv, the history is sent as a CSV file instead. Without a mention, it's the caller's.
+async fn trade_history(ctx: &Context, msg: &Message, args: Args, to_csv: bool) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+
+    let mut tokens = args.rest().split_whitespace();
+    let user = match tokens.next() {
+        Some(mention) => match serenity::utils::parse_username(mention) {
+            Some(id) => UserId(id),
+            None => {
+                digest.set_content( format!("'{}' isn't a user. Mention them, e.g. '!trades history @someone'.", mention) );
+                return digest;
+            }
+        },
+        None => msg.author.id,
+    };
+    let page_size = tokens.find_map(Pager::parse_page_size).unwrap_or(Pager::DEFAULT_PAGE_SIZE);
+    let history = match store.proposals_for(user) {
+        Ok(p) => Ledger::history(user, &p),
+        Err(why) => {
+            store_error(&mut digest, why);
+            return digest;
+        }
+    };
+    let whose = if user == msg.author.id { String::from("You haven't") } else { format!("<@{}> hasn't", user) };
+    if history.is_empty() {
+        digest.set_content( format!("{} completed any trades yet.", whose) );
+    } else if to_csv {
+        match Ledger::to_csv(user, &history) {
+            Ok(file) => {
+                digest.set_content( format!("Here is the trade history of <@{}>.", user) );
+                digest.add_file(format!("trades-{}.csv", user), file);
+            }
+            Err(why) => digest.set_content( format!("The trade history could not be exported: {}", why) ),
+        }
+    } else {
+        let lines = history.iter().map(|r| r.line()).collect();
+        let intro = format!("<@{}> has completed {} trades.", user, history.len());
+        digest.set_pages( Pager::paginate("Trade History", Colour::GOLD, Some(&intro), &[(String::from("Trades"), lines)], page_size) );
+    }
+    digest
+}
+
+// Leaves feedback on a completed trade, e.g. '!trades feedback 12 positive Fast shipping!'.
+async fn leave_feedback(ctx: &Context, msg: &Message, args: Args) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+
+    let id = match proposal_id(&args) {
+        Ok(id) => id,
+        Err(why) => {
+            digest.set_content(why);
+            return digest;
+        }
+    };
+    let rest = args.rest().trim_start();
+    let rest = rest.split_once(char::is_whitespace).map_or("", |(_, r)| r).trim_start();
+    let (rating, comment) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let rating = match rating.parse::<Reputation::Rating>() {
+        Ok(r) => r,
+        Err(_) => {
+            digest.set_content( String::from("Rate the trade as positive, neutral or negative, e.g. '!trades feedback 12 positive Fast shipping!'.") );
+            return digest;
+        }
+    };
+    let feedback = match Reputation::new(msg.author.id, rating, comment) {
+        Ok(f) => f,
+        Err(why) => {
+            digest.set_content(why);
+            return digest;
+        }
+    };
+    let mut outcome: Result<UserId, String> = Err(format!("There is no trade #{}.", id));
+    let result = store.update_proposal(id, &mut |proposal| {
+        outcome = proposal
+            .leave_feedback(feedback.clone())
+            .map(|_| if proposal.from == msg.author.id { proposal.to } else { proposal.from });
+    });
+    match result {
+        Ok(_) => match outcome {
+            Ok(other) => digest.set_content( format!("Your {} feedback for <@{}> on trade #{} was recorded.", rating, other, id) ),
+            Err(why) => digest.set_content(why),
+        },
+        Err(why) => store_error(&mut digest, why),
+    }
+    digest
+}
+
+// Trade proposals: '!trade offer', 'accept', 'decline', 'counter', 'withdraw', 'complete', 'cancel' and 'view',
+// and completed trades: '!trades history', 'export' and 'feedback'.
+#[command("trade")]
+#[aliases("trades")]
+async fn trade(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut digest: Response::Response = Response::new();
+    let mut new_args: Args = Args::new(args.rest(), &[Delimiter::Single(' '), Delimiter::Single('\n')]);
+    let task = new_args.single::<String>().unwrap_or_default();
+    if task.as_str() == "" || task.as_str() == "view" {
+        digest = view_trades(ctx, msg, new_args).await;
+    } else if task.as_str() == "offer" {
+        digest = offer_trade(ctx, msg, new_args).await;
+    } else if task.as_str() == "accept" {
+        digest = accept_trade(ctx, msg, new_args).await;
+    } else if task.as_str() == "decline" {
+        digest = close_trade(ctx, msg, new_args, Proposal::Status::Declined).await;
+    } else if task.as_str() == "withdraw" {
+        digest = close_trade(ctx, msg, new_args, Proposal::Status::Withdrawn).await;
+    } else if task.as_str() == "counter" {
+        digest = counter_trade(ctx, msg, new_args).await;
+    } else if task.as_str() == "complete" {
+        digest = complete_trade(ctx, msg, new_args).await;
+    } else if task.as_str() == "cancel" {
+        digest = cancel_trade(ctx, msg, new_args).await;
+    } else if task.as_str() == "history" {
+        digest = trade_history(ctx, msg, new_args, false).await;
+    } else if task.as_str() == "export" {
+        digest = trade_history(ctx, msg, new_args, true).await;
+    } else if task.as_str() == "feedback" {
+        digest = leave_feedback(ctx, msg, new_args).await;
+    } else {
+        digest.set_content( String::from("You need to specify what you want to do: offer, accept, decline, counter, withdraw, complete, cancel, view, history, export or feedback.") );
+    }
+    Response::send_message( digest, ctx, msg ).await
+}
+
+// Shows a user's reputation and the feedback they have received, e.g. '!reputation @someone'.
+// Without a mention, it's the caller's.
+#[command("reputation")]
+#[aliases("rep")]
+async fn reputation(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+
+    let mut tokens = args.rest().split_whitespace();
+    let user = match tokens.next() {
+        Some(mention) => match serenity::utils::parse_username(mention) {
+            Some(id) => UserId(id),
+            None => {
+                digest.set_content( format!("'{}' isn't a user. Mention them, e.g. '!reputation @someone'.", mention) );
+                return Response::send_message( digest, ctx, msg ).await;
+            }
+        },
+        None => msg.author.id,
+    };
+    let page_size = tokens.find_map(Pager::parse_page_size).unwrap_or(Pager::DEFAULT_PAGE_SIZE);
+    let proposals = match store.proposals_for(user) {
+        Ok(p) => p,
+        Err(why) => {
+            store_error(&mut digest, why);
+            return Response::send_message( digest, ctx, msg ).await;
+        }
+    };
+    let trades = Ledger::history(user, &proposals).len();
+    let intro = format!("<@{}> has completed {} trades and has {}.", user, trades, Reputation::of(user, &proposals));
+    let lines = Reputation::received(user, &proposals)
+        .iter()
+        .map(|(id, f)| {
+            let comment = f.comment.as_ref().map(|c| format!(": {}", c)).unwrap_or_default();
+            format!("<t:{}:d> {} from <@{}> on #{}{}", f.time, f.rating, f.from, id, comment)
+        })
+        .collect();
+    digest.set_pages( Pager::paginate("Reputation", Colour::GOLD, Some(&intro), &[(String::from("Feedback"), lines)], page_size) );
+    Response::send_message( digest, ctx, msg ).await
+}
+
+// Shows a user's profile, e.g. '!profile @someone'. Without a mention, it's the caller's.
+async fn view_profile(ctx: &Context, msg: &Message, mut args: Args) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+
+    let user = match args.single::<String>() {
+        Ok(mention) => match serenity::utils::parse_username(&mention) {
+            Some(id) => UserId(id),
+            None => {
+                digest.set_content( format!("'{}' isn't a user. Mention them, e.g. '!profile @someone'.", mention) );
+                return digest;
+            }
+        },
+        Err(_) => msg.author.id,
+    };
+    match store.profile(user) {
+        Ok(Some(profile)) if !profile.is_empty() => digest.set_content( format!("<@{}>'s profile:\n{}", user, profile) ),
+        Ok(_) if user == msg.author.id => digest.set_content( String::from("You haven't filled in your profile. Use e.g. '!profile set city Portland' or '!profile set method in-person'.") ),
+        Ok(_) => digest.set_content( format!("<@{}> hasn't filled in their profile.", user) ),
+        Err(why) => store_error(&mut digest, why),
+    }
+    digest
+}
+
+// Sets or clears a field of the caller's profile, e.g. '!profile set formats Modern, Legacy'.
+async fn edit_profile(ctx: &Context, msg: &Message, mut args: Args, clear: bool) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+
+    let field = match args.single::<String>() {
+        Ok(f) => f,
+        Err(_) => {
+            digest.set_content( format!("You need to specify a field: {}.", Profile::FIELDS.join(", ")) );
+            return digest;
+        }
+    };
+    let value = if clear { "" } else { args.rest() };
+    if !clear && value.trim().is_empty() {
+        digest.set_content( format!("You need to give a value for your {}, or use '!profile clear {}'.", field, field) );
+        return digest;
+    }
+    let mut outcome = Ok(());
+    let result = store.update(msg.author.id, true, &mut |trader| {
+        outcome = trader.profile_mut().set(&field, value);
+    });
+    match (result, outcome) {
+        (Err(why), _) => store_error(&mut digest, why),
+        (_, Err(why)) => digest.set_content(why),
+        (Ok(_), Ok(())) if clear => digest.set_content( format!("Your {} was cleared.", field) ),
+        (Ok(_), Ok(())) => digest.set_content( format!("Your {} was set to {}.", field, value.trim()) ),
+    }
+    digest
+}
+
+// Where and how the user trades: '!profile', '!profile @someone', '!profile set <field> <value>'
+// and '!profile clear <field>'. Matches and searches use profiles to rank partners and to
+// leave out those that can't meet someone who only trades in person.
+#[command("profile")]
+async fn profile(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut digest: Response::Response = Response::new();
+    let mut new_args: Args = Args::new(args.rest(), &[Delimiter::Single(' '), Delimiter::Single('\n')]);
+    let task = new_args.current().unwrap_or_default().to_string();
+    if task.as_str() == "set" {
+        new_args.advance();
+        digest = edit_profile(ctx, msg, new_args, false).await;
+    } else if task.as_str() == "clear" {
+        new_args.advance();
+        digest = edit_profile(ctx, msg, new_args, true).await;
+    } else if task.as_str() == "" || task.starts_with("<@") {
+        digest = view_profile(ctx, msg, new_args).await;
+    } else {
+        digest.set_content( String::from("You need to specify what you want to do: set, clear, or mention someone to view their profile.") );
+    }
+    Response::send_message( digest, ctx, msg ).await
+}
+
+// Marks the caller as trading at a store for a while, e.g. '!lft start Dragon's Den 2h'.
+// Without a store, the one in their profile is used.
+async fn start_session(ctx: &Context, msg: &Message, args: Args) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let sessions = data.get::<Session::Sessions>().unwrap();
+
+    let mut tokens: Vec<&str> = args.rest().split_whitespace().collect();
+    let duration = match tokens.last().and_then(|t| Session::parse_duration(t)) {
+        Some(d) => {
+            tokens.pop();
+            d
+        }
+        None => Session::DEFAULT_DURATION,
+    };
+    let location = if tokens.is_empty() {
+        match store.profile(msg.author.id) {
+            Ok(profile) => profile.and_then(|p| p.store),
+            Err(why) => {
+                store_error(&mut digest, why);
+                return digest;
+            }
+        }
+    } else {
+        Some(tokens.join(" "))
+    };
+    let location = match location {
+        Some(l) => l,
+        None => {
+            digest.set_content( String::from("You need to say where you're trading, e.g. '!lft start Dragon's Den 2h', or add your store with '!profile set store ...'.") );
+            return digest;
+        }
+    };
+    let session = match Session::new(location, duration) {
+        Ok(s) => s,
+        Err(why) => {
+            digest.set_content(why);
+            return digest;
+        }
+    };
+    digest.set_content( format!("You're looking for trades {}. Use '!lft matches' to see who there has what you need.", session) );
+    Session::start(sessions, msg.author.id, session);
+    digest
+}
+
+async fn stop_session(ctx: &Context, msg: &Message) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let sessions = data.get::<Session::Sessions>().unwrap();
+
+    if Session::stop(sessions, msg.author.id) {
+        digest.set_content( String::from("You're no longer looking for trades.") );
+    } else {
+        digest.set_content( String::from("You aren't looking for trades right now.") );
+    }
+    digest
+}
+
+// Shows who else is trading at the caller's store right now.
+async fn view_session(ctx: &Context, msg: &Message) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let sessions = data.get::<Session::Sessions>().unwrap();
+
+    let session = match Session::get(sessions, msg.author.id) {
+        Some(s) => s,
+        None => {
+            digest.set_content( String::from("You aren't looking for trades right now. Start with '!lft start [store] [duration]'.") );
+            return digest;
+        }
+    };
+    let others: Vec<String> = Session::at(sessions, &session.store)
+        .iter()
+        .filter(|(id, _)| *id != msg.author.id)
+        .map(|(id, s)| format!("\n<@{}> until <t:{}:t>", id, s.ends))
+        .collect();
+    if others.is_empty() {
+        digest.set_content( format!("You're looking for trades {}. No one else there is yet.", session) );
+    } else {
+        digest.set_content( format!("You're looking for trades {}, along with:{}", session, others.concat()) );
+    }
+    digest
+}
+
+// Matches the caller's lists against the public lists of everyone trading at the same store.
+// 'size:30' shows 30 matches per page.
+async fn session_matches(ctx: &Context, msg: &Message, args: Args) -> Response::Response {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let db = data.get::<CardDB::CardDB>().unwrap();
+    let sessions = data.get::<Session::Sessions>().unwrap();
+    let page_size = args.rest().split_whitespace().find_map(Pager::parse_page_size).unwrap_or(Pager::DEFAULT_PAGE_SIZE);
+
+    let session = match Session::get(sessions, msg.author.id) {
+        Some(s) => s,
+        None => {
+            digest.set_content( String::from("You aren't looking for trades right now. Start with '!lft start [store] [duration]'.") );
+            return digest;
+        }
+    };
+    let active: HashSet<UserId> = Session::at(sessions, &session.store).into_iter().map(|(id, _)| id).collect();
+    let lists = store.get(msg.author.id).and_then(|mine| Ok((mine, store.public()?)));
+    let (mine, others) = match lists {
+        Ok(l) => l,
+        Err(why) => {
+            store_error(&mut digest, why);
+            return digest;
+        }
+    };
+    let mine = match mine {
+        Some(m) => m,
+        None => {
+            digest.set_content( String::from(NO_TRADELIST) );
+            return digest;
+        }
+    };
+    let my_lists: Vec<&Tradelist::Tradelist> = mine.lists().map(|(_, l)| l).collect();
+    let mut has = Vec::new();
+    let mut wants = Vec::new();
+    for (id, name, other) in others.iter().filter(|(id, _, _)| *id != msg.author.id && active.contains(id)) {
+        let (their_haves, their_wants) = match_lines(db, &my_lists, &format!("<@{}>", id), name, other);
+        has.extend(their_haves);
+        wants.extend(their_wants);
+    }
+    if has.is_empty() && wants.is_empty() {
+        digest.set_content( format!("No one trading at {} right now has what you want or wants what you have.", session.store) );
+    } else {
+        let intro = format!("Here are your matches at {} right now:", session.store);
+        digest.set_pages( match_pages("Your Matches", &intro, has, wants, page_size) );
+    }
+    digest
+}
+
+// Looking for trade: '!lft start [store] [duration]' marks the caller as trading at a store
+// for a while (3 hours unless given, e.g. '90m' or '2h', and at most 12), '!lft matches'
+// matches them with everyone else trading there, '!lft stop' ends it early and '!lft' shows
+// who is there.
+#[command("lft")]
+async fn lft(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut digest: Response::Response = Response::new();
+    let mut new_args: Args = Args::new(args.rest(), &[Delimiter::Single(' '), Delimiter::Single('\n')]);
+    let task = new_args.single::<String>().unwrap_or_default();
+    if task.as_str() == "" || task.as_str() == "view" {
+        digest = view_session(ctx, msg).await;
+    } else if task.as_str() == "start" {
+        digest = start_session(ctx, msg, new_args).await;
+    } else if task.as_str() == "stop" {
+        digest = stop_session(ctx, msg).await;
+    } else if task.as_str() == "matches" {
+        digest = session_matches(ctx, msg, new_args).await;
+    } else {
+        digest.set_content( String::from("You need to specify what you want to do: start, stop, matches or view.") );
+    }
+    Response::send_message( digest, ctx, msg ).await
+}
+
+// The guild's settings: '!settings' shows them and '!settings scope <scope>' sets the scope
+// that matches and searches use unless told otherwise, e.g. '!settings scope store' for a
+// guild that covers one store. '!settings scope default' goes back to the bot's default.
+#[command("settings")]
+#[only_in(guilds)]
+#[required_permissions("MANAGE_GUILD")]
+async fn settings(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut digest: Response::Response = Response::new();
+    let data = ctx.data.read().await;
+    let store = data.get::<TradeStore::Store>().unwrap();
+    let fallback = *data.get::<DefaultScope>().unwrap();
+    let guild = msg.guild_id.unwrap();
+
+    let mut tokens = args.rest().split_whitespace();
+    let task = tokens.next().unwrap_or_default().to_lowercase();
+    let value = tokens.next().unwrap_or_default();
+    if task.is_empty() {
+        match store.settings(guild) {
+            Ok(settings) => match settings.scope {
+                Some(scope) => digest.set_content( format!("Matches and searches here look within: {}.", scope) ),
+                None => digest.set_content( format!("Matches and searches here look within the bot's default: {}. Change it with '!settings scope <store, city, region or ship>'.", fallback) ),
+            },
+            Err(why) => store_error(&mut digest, why),
+        }
+    } else if task == "scope" {
+        let scope = if value.eq_ignore_ascii_case("default") {
+            Ok(None)
+        } else {
+            value.parse::<Profile::Scope>().map(Some).map_err(|_| format!("'{}' isn't a scope. Try store, city, region, ship or default.", value))
+        };
+        match scope {
+            Ok(scope) => match store.update_settings(guild, &mut |settings| settings.scope = scope) {
+                Ok(()) => digest.set_content( format!("Matches and searches here will look within: {}.", scope.unwrap_or(fallback)) ),
+                Err(why) => store_error(&mut digest, why),
+            },
+            Err(why) => digest.set_content(why),
+        }
+    } else {
+        digest.set_content( String::from("You need to specify what you want to change: scope.") );
+    }
+    Response::send_message( digest, ctx, msg ).await
+}
@@ -0,0 +1,122 @@
+pub mod Migrations {
+
+    use std::path::{Path, PathBuf};
+
+    use serde_json::{json, Map, Value};
+
+    // The version of the json snapshot that this build reads and writes. Bump it and add a
+    // step to SNAPSHOT_STEPS whenever a change to CardEntry, Tradelist, etc. would stop an
+    // older snapshot from loading.
+    pub const SNAPSHOT_VERSION: u32 = 2;
+
+    type SnapshotStep = fn(Value) -> Result<Value, String>;
+
+    // Step i upgrades a snapshot from version i to version i + 1.
+    const SNAPSHOT_STEPS: [(&str, SnapshotStep); SNAPSHOT_VERSION as usize] = [
+        ("Wrap the tradelists in a versioned snapshot", wrap_snapshot),
+        ("Move each tradelist into a trader's list of named lists", split_traders),
+    ];
+
+    // Snapshots from before versioning were a bare map of user ids to tradelists.
+    fn wrap_snapshot(data: Value) -> Result<Value, String> {
+        if !data.is_object() {
+            return Err(String::from("the snapshot is not a map of tradelists"));
+        }
+        Ok(json!({ "version": 1, "tradelists": data }))
+    }
+
+    // Each tradelist becomes the only list of a trader. The name is spelled out rather than
+    // taken from Trader::DEFAULT_LIST so that this step never changes.
+    fn split_traders(mut data: Value) -> Result<Value, String> {
+        let lists = match data.as_object_mut().and_then(|d| d.remove("tradelists")) {
+            Some(Value::Object(lists)) => lists,
+            _ => return Err(String::from("the snapshot has no tradelists")),
+        };
+        let traders: Map<String, Value> = lists
+            .into_iter()
+            .map(|(id, list)| (id, json!({ "lists": { "Main": list }, "default": "Main" })))
+            .collect();
+        data["traders"] = Value::Object(traders);
+        Ok(data)
+    }
+
+    // User ids are numbers, so an unversioned map of tradelists never has a version key.
+    pub fn snapshot_version(data: &Value) -> u32 {
+        data.get("version").and_then(Value::as_u64).map_or(0, |v| v as u32)
+    }
+
+    // Upgrades the snapshot to the current version one step at a time. Returns the upgraded
+    // snapshot and a description of each step that was applied.
+    pub fn migrate_snapshot(mut data: Value) -> Result<(Value, Vec<String>), String> {
+        let version = snapshot_version(&data);
+        if version > SNAPSHOT_VERSION {
+            return Err(format!(
+                "the snapshot is version {}, but this build only knows up to version {}",
+                version, SNAPSHOT_VERSION
+            ));
+        }
+        let mut applied = Vec::new();
+        for (from, (name, step)) in SNAPSHOT_STEPS.iter().enumerate().skip(version as usize) {
+            data = step(data).map_err(|e| format!("{}: {}", describe(from as u32, name), e))?;
+            if let Some(obj) = data.as_object_mut() {
+                obj.insert(String::from("version"), json!(from + 1));
+            }
+            applied.push(describe(from as u32, name));
+        }
+        Ok((data, applied))
+    }
+
+    pub fn describe(from: u32, name: &str) -> String {
+        format!("version {} to {}: {}", from, from + 1, name)
+    }
+
+    // Where a file is copied before it is migrated, e.g. tradelists.json.v0.bak. If a
+    // migration failed after an earlier backup, that backup is kept and the next one gets a
+    // number, e.g. tradelists.json.v0.2.bak.
+    pub fn backup_path(path: &Path, version: u32) -> PathBuf {
+        let mut attempt = 1;
+        loop {
+            let mut name = path.as_os_str().to_owned();
+            match attempt {
+                1 => name.push(format!(".v{}.bak", version)),
+                n => name.push(format!(".v{}.{}.bak", version, n)),
+            }
+            let digest = PathBuf::from(name);
+            if !digest.exists() {
+                return digest;
+            }
+            attempt += 1;
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn old_snapshots_are_upgraded_step_by_step() {
+            let old = json!({ "1234": { "cards": {}, "is_public": true } });
+            let (data, applied) = migrate_snapshot(old).unwrap();
+            assert_eq!(applied.len(), SNAPSHOT_VERSION as usize);
+            assert_eq!(snapshot_version(&data), SNAPSHOT_VERSION);
+            assert_eq!(data["traders"]["1234"]["default"], "Main");
+            assert_eq!(data["traders"]["1234"]["lists"]["Main"]["is_public"], true);
+            assert!(migrate_snapshot(json!({ "version": SNAPSHOT_VERSION + 1 })).is_err());
+        }
+
+        #[test]
+        fn backups_never_overwrite_each_other() {
+            let dir = std::env::temp_dir().join(format!("tavern-backup-test-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("tavern.db");
+            let first = backup_path(&path, 3);
+            assert_eq!(first, dir.join("tavern.db.v3.bak"));
+            std::fs::write(&first, "").unwrap();
+            let second = backup_path(&path, 3);
+            assert_eq!(second, dir.join("tavern.db.v3.2.bak"));
+            std::fs::write(&second, "").unwrap();
+            assert_eq!(backup_path(&path, 3), dir.join("tavern.db.v3.3.bak"));
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
@@ -0,0 +1,191 @@
+pub mod Notifier {
+
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use serenity::http::Http;
+    use serenity::model::id::UserId;
+    use serenity::prelude::*;
+
+    use crate::card_entry::CardEntry;
+    use crate::card_list::CardList;
+    use crate::trade_store::TradeStore::{StoreResult, TradeStore};
+    use crate::trader::Trader;
+
+    // Additions are collected and sent together every few minutes, so a big import turns
+    // into one message per user rather than one per card.
+    pub const FLUSH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+    // Messages stay well below Discord's 2000 character limit.
+    pub const MAX_LINES: usize = 15;
+
+    // Cards that a user added to one of their public lists.
+    pub struct Addition {
+        pub owner: UserId,
+        pub list: String,
+        pub is_want: bool,
+        pub cards: Vec<CardEntry::CardEntry>,
+    }
+
+    // The additions waiting for the next flush.
+    pub struct Queue {
+        pending: Mutex<Vec<Addition>>,
+    }
+
+    impl TypeMapKey for Queue {
+        type Value = Arc<Queue>;
+    }
+
+    pub fn new() -> Queue {
+        Queue { pending: Mutex::new(Vec::new()) }
+    }
+
+    impl Queue {
+        pub fn push(&self, addition: Addition) {
+            if addition.cards.is_empty() {
+                return;
+            }
+            if let Ok(mut pending) = self.pending.lock() {
+                pending.push(addition);
+            }
+        }
+
+        pub fn take(&self) -> Vec<Addition> {
+            self.pending.lock().map(|mut p| std::mem::take(&mut *p)).unwrap_or_default()
+        }
+    }
+
+    // Makes a change to the trader and returns what it added to their public lists, counting
+    // every card in a list that the change made public. Used where cards show up in public
+    // lists without being added by hand, e.g. when the collection behind a linked list grows,
+    // a completed trade brings in new cards or a list is made public.
+    pub fn watch<F: FnOnce(&mut Trader::Trader)>(owner: UserId, trader: &mut Trader::Trader, change: F) -> Vec<Addition> {
+        // Lists that aren't public count as empty, since nobody could see their cards
+        let before: Vec<(String, CardList::CardList, CardList::CardList)> = trader
+            .lists()
+            .map(|(name, l)| {
+                if l.is_public() {
+                    (name.clone(), l.haves().clone(), l.wants().clone())
+                } else {
+                    (name.clone(), CardList::new(), CardList::new())
+                }
+            })
+            .collect();
+        change(trader);
+        let mut digest = Vec::new();
+        for (name, haves, wants) in before {
+            let list = match trader.get(Some(&name)) {
+                Ok(l) if l.is_public() => l,
+                _ => continue,
+            };
+            let added_haves = list.haves().added_since(&haves);
+            let added_wants = list.wants().added_since(&wants);
+            digest.push(Addition { owner, list: name.clone(), is_want: false, cards: added_haves });
+            digest.push(Addition { owner, list: name, is_want: true, cards: added_wants });
+        }
+        digest
+    }
+
+    // What to tell each user about the additions: new haves go to those who want them, and
+    // new wants go to those who have them in a public list. Users who turned notifications
+    // off in their profile are left out.
+    pub fn digests(store: &dyn TradeStore, additions: &[Addition]) -> StoreResult<BTreeMap<UserId, Vec<String>>> {
+        let mut digest: BTreeMap<UserId, Vec<String>> = BTreeMap::new();
+        if additions.is_empty() {
+            return Ok(digest);
+        }
+        let traders = store.all()?;
+        for addition in additions {
+            let mut added = CardList::new();
+            for card in &addition.cards {
+                added.add(card.clone());
+            }
+            for (id, trader) in traders.iter().filter(|(id, t)| *id != addition.owner && t.profile().notify != Some(false)) {
+                let lines = digest.entry(*id).or_default();
+                for (name, list) in trader.lists() {
+                    if addition.is_want {
+                        if !list.is_public() {
+                            continue;
+                        }
+                        for want in added.iter() {
+                            for have in list.haves().matching(want) {
+                                lines.push(format!("<@{}> now wants {}, and you have {} in \"{}\"", addition.owner, want, have, name));
+                            }
+                        }
+                    } else {
+                        for want in list.wants().iter() {
+                            for have in added.matching(want) {
+                                lines.push(format!("<@{}> added {} to \"{}\" (you want {})", addition.owner, have, addition.list, want));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        digest.retain(|_, lines| !lines.is_empty());
+        Ok(digest)
+    }
+
+    fn message(lines: &[String]) -> String {
+        let mut digest = String::from("New matches for your tradelists:");
+        for line in lines.iter().take(MAX_LINES) {
+            digest += &format!("\n{}", line);
+        }
+        if lines.len() > MAX_LINES {
+            digest += &format!("\n...and {} more. Use '!matches' to see them all.", lines.len() - MAX_LINES);
+        }
+        digest
+    }
+
+    // Sends the waiting additions to the users they concern, one message each.
+    pub async fn flush(http: &Http, store: &dyn TradeStore, queue: &Queue) {
+        let digests = match digests(store, &queue.take()) {
+            Ok(d) => d,
+            Err(why) => {
+                println!("Could not look for new matches: {}", why);
+                return;
+            }
+        };
+        for (user, lines) in digests {
+            let sent = match user.create_dm_channel(http).await {
+                Ok(channel) => channel.say(http, message(&lines)).await.map(|_| ()),
+                Err(why) => Err(why),
+            };
+            if let Err(why) = sent {
+                println!("Could not notify {}: {:?}", user, why);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::card::Card;
+        use crate::card_db::CardDB;
+        use crate::utils::Types::CardCount;
+
+        fn entry(name: &str, count: CardCount) -> CardEntry::CardEntry {
+            let db = CardDB::sample();
+            CardEntry::new(count, Card::new(db.get_card(&name.to_string()).unwrap().clone(), String::new()))
+        }
+
+        fn counts(additions: &[Addition]) -> Vec<(bool, CardCount)> {
+            additions.iter().filter(|a| !a.cards.is_empty()).map(|a| (a.is_want, a.cards.iter().map(|c| c.count).sum())).collect()
+        }
+
+        #[test]
+        fn making_a_list_public_puts_up_everything_in_it() {
+            let mut trader = Trader::new();
+            let list = trader.get_mut(None).unwrap();
+            list.add_card(entry("Lightning Bolt", 4));
+            list.add_want(entry("Duress", 2));
+            let added = watch(UserId(1), &mut trader, |t| t.get_mut(None).unwrap().add_card(entry("Counterspell", 1)));
+            assert!(counts(&added).is_empty());
+            let added = watch(UserId(1), &mut trader, |t| t.get_mut(None).unwrap().set_public());
+            assert_eq!(counts(&added), vec![(false, 5), (true, 2)]);
+            let added = watch(UserId(1), &mut trader, |t| t.get_mut(None).unwrap().add_card(entry("Lightning Bolt", 1)));
+            assert_eq!(counts(&added), vec![(false, 1)]);
+        }
+    }
+}
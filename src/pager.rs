@@ -0,0 +1,164 @@
+pub mod Pager {
+
+    use std::time::{Duration, Instant};
+
+    use dashmap::DashMap;
+    use serenity::model::channel::{Reaction, ReactionType};
+    use serenity::model::id::{MessageId, UserId};
+    use serenity::prelude::*;
+    use serenity::utils::Colour;
+
+    use crate::response::Response;
+
+    // Discord's limits on embeds. Pages stay a little under the total so that the title
+    // and field names always fit.
+    const MAX_FIELD_LENGTH: usize = 1024;
+    const MAX_PAGE_LENGTH: usize = 5000;
+    const MAX_FIELDS: usize = 25;
+
+    pub const DEFAULT_PAGE_SIZE: usize = 20;
+    pub const MAX_PAGE_SIZE: usize = 50;
+
+    pub const PREVIOUS: &str = "◀️";
+    pub const NEXT: &str = "▶️";
+
+    // Pages can be turned for this long after they are sent.
+    const PAGE_LIFETIME: Duration = Duration::from_secs(30 * 60);
+
+    // Reads a page size option like "size:30". Sizes are capped at MAX_PAGE_SIZE.
+    pub fn parse_page_size(token: &str) -> Option<usize> {
+        let size = token.strip_prefix("size:")?.parse::<usize>().ok()?;
+        Some(size.clamp(1, MAX_PAGE_SIZE))
+    }
+
+    // A single line has to fit in a field on its own.
+    fn clip(line: &str) -> String {
+        if line.chars().count() < MAX_FIELD_LENGTH {
+            return line.to_string();
+        }
+        let mut digest: String = line.chars().take(MAX_FIELD_LENGTH - 4).collect();
+        digest.push_str("...");
+        digest
+    }
+
+    // Splits groups of lines (e.g. "Haves" and "Wants") into embeds that each show at most
+    // page_size lines and fit within Discord's limits. A group that doesn't fit on one page
+    // or in one field carries on in a field marked as continued. The intro, if any, is shown
+    // at the top of every page.
+    pub fn paginate(title: &str, colour: Colour, intro: Option<&str>, groups: &[(String, Vec<String>)], page_size: usize) -> Vec<Response::EmbedSpoof> {
+        let page_size = page_size.clamp(1, MAX_PAGE_SIZE);
+        let mut pages: Vec<Vec<(String, String)>> = Vec::new();
+        let mut fields: Vec<(String, String)> = Vec::new();
+        let mut lines = 0;
+        let mut length = 0;
+        for (group, entries) in groups {
+            let continued = format!("{} (continued)", group);
+            let mut name = group.clone();
+            let mut value = String::new();
+            let none = [String::from("None")];
+            let entries = if entries.is_empty() { &none[..] } else { &entries[..] };
+            for line in entries {
+                let line = clip(line);
+                let page_full = lines >= page_size
+                    || length + name.len() + value.len() + line.len() >= MAX_PAGE_LENGTH
+                    // Leaves room for the intro and a field split by this line
+                    || fields.len() + 2 >= MAX_FIELDS;
+                if page_full {
+                    if !value.is_empty() {
+                        fields.push((name, std::mem::take(&mut value)));
+                        name = continued.clone();
+                    }
+                    pages.push(std::mem::take(&mut fields));
+                    lines = 0;
+                    length = 0;
+                } else if value.len() + line.len() >= MAX_FIELD_LENGTH {
+                    length += name.len() + value.len();
+                    fields.push((name, std::mem::take(&mut value)));
+                    name = continued.clone();
+                }
+                value += &line;
+                value.push('\n');
+                lines += 1;
+            }
+            length += name.len() + value.len();
+            fields.push((name, value));
+        }
+        if !fields.is_empty() || pages.is_empty() {
+            pages.push(fields);
+        }
+
+        let count = pages.len();
+        pages
+            .into_iter()
+            .enumerate()
+            .map(|(i, page)| {
+                let mut digest = Response::EmbedSpoof {
+                    title: if count > 1 { format!("{} (page {}/{})", title, i + 1, count) } else { title.to_string() },
+                    colour,
+                    fields: Vec::new(),
+                };
+                if let Some(intro) = intro {
+                    digest.fields.push((intro.to_string(), String::from("\u{200b}"), false));
+                }
+                digest.fields.extend(page.into_iter().map(|(name, value)| (name, value, false)));
+                digest
+            })
+            .collect()
+    }
+
+    // A paginated message that can still be turned.
+    pub struct Pages {
+        owner: UserId,
+        pages: Vec<Response::EmbedSpoof>,
+        current: usize,
+        sent: Instant,
+    }
+
+    pub struct Paginated;
+
+    impl TypeMapKey for Paginated {
+        type Value = DashMap<MessageId, Pages>;
+    }
+
+    // Remembers a message's pages so that its owner can turn them with reactions.
+    pub fn track(paginated: &DashMap<MessageId, Pages>, message: MessageId, owner: UserId, pages: Vec<Response::EmbedSpoof>) {
+        paginated.retain(|_, p| p.sent.elapsed() < PAGE_LIFETIME);
+        paginated.insert(message, Pages { owner, pages, current: 0, sent: Instant::now() });
+    }
+
+    // Turns the page when the owner of a paginated message reacts with an arrow.
+    pub async fn turn_page(ctx: &Context, reaction: &Reaction) {
+        let forward = match &reaction.emoji {
+            ReactionType::Unicode(e) if e == NEXT => true,
+            ReactionType::Unicode(e) if e == PREVIOUS => false,
+            _ => return,
+        };
+        let page = {
+            let data = ctx.data.read().await;
+            let paginated = match data.get::<Paginated>() {
+                Some(p) => p,
+                None => return,
+            };
+            let mut pages = match paginated.get_mut(&reaction.message_id) {
+                Some(p) => p,
+                None => return,
+            };
+            if reaction.user_id != Some(pages.owner) {
+                return;
+            }
+            let count = pages.pages.len();
+            pages.current = if forward { (pages.current + 1) % count } else { (pages.current + count - 1) % count };
+            pages.pages[pages.current].clone()
+        };
+        let edit = reaction
+            .channel_id
+            .edit_message(&ctx.http, reaction.message_id, |m| m.embed(|e| Response::build_embed(e, page)))
+            .await;
+        if let Err(why) = edit {
+            println!("Could not turn the page: {:?}", why);
+        }
+        // Removing the reaction lets the owner press the same arrow again. This needs the
+        // Manage Messages permission, and isn't possible in DMs, so failures are ignored.
+        let _ = reaction.delete(&ctx.http).await;
+    }
+}
@@ -0,0 +1,234 @@
+pub mod Profile {
+
+    use std::fmt;
+    use std::str::FromStr;
+
+    use serde::{Deserialize, Serialize};
+
+    // Profile fields are shown in embeds and matches, so they are kept short.
+    pub const MAX_FIELD_LENGTH: usize = 100;
+
+    // How a trader is willing to get cards to and from their partners.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum Method {
+        Ship,
+        InPerson,
+        Both,
+    }
+
+    impl FromStr for Method {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.trim().to_lowercase().replace(['-', '_'], " ").as_str() {
+                "ship" | "shipping" | "mail" => Ok(Method::Ship),
+                "in person" | "inperson" | "local" | "meet" => Ok(Method::InPerson),
+                "both" | "any" | "either" => Ok(Method::Both),
+                _ => Err(()),
+            }
+        }
+    }
+
+    impl fmt::Display for Method {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let name = match self {
+                Method::Ship => "ships",
+                Method::InPerson => "in person only",
+                Method::Both => "ships or meets in person",
+            };
+            write!(f, "{}", name)
+        }
+    }
+
+    // How far afield matches and searches look for partners. Ship takes anyone that the
+    // caller can trade with at all, the others only those they can meet.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum Scope {
+        Store,
+        City,
+        Region,
+        Ship,
+    }
+
+    impl FromStr for Scope {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.trim().to_lowercase().as_str() {
+                "store" | "lgs" => Ok(Scope::Store),
+                "city" => Ok(Scope::City),
+                "region" | "country" => Ok(Scope::Region),
+                "ship" | "shipping" | "any" | "anywhere" => Ok(Scope::Ship),
+                _ => Err(()),
+            }
+        }
+    }
+
+    impl fmt::Display for Scope {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let name = match self {
+                Scope::Store => "store",
+                Scope::City => "city",
+                Scope::Region => "region",
+                Scope::Ship => "ship",
+            };
+            write!(f, "{}", name)
+        }
+    }
+
+    // Reads a scope option like "scope:city". Returns None if the token isn't one, and an
+    // error if it is one but names no scope.
+    pub fn parse_scope(token: &str) -> Option<Result<Scope, String>> {
+        let (key, value) = token.split_once(':')?;
+        if !key.eq_ignore_ascii_case("scope") {
+            return None;
+        }
+        Some(value.parse().map_err(|_| format!("'{}' isn't a scope. Try store, city, region or ship.", value)))
+    }
+
+    // What a trader tells others about how and where they trade. Every field is optional.
+    #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct Profile {
+        pub region: Option<String>,
+        pub city: Option<String>,
+        pub method: Option<Method>,
+        pub store: Option<String>,
+        pub formats: Vec<String>,
+        pub timezone: Option<String>,
+        pub contact: Option<String>,
+        // Whether they want to hear about new matches. Unset means they do.
+        pub notify: Option<bool>,
+    }
+
+    pub fn new() -> Profile {
+        Profile::default()
+    }
+
+    pub const FIELDS: [&str; 8] = ["region", "city", "method", "store", "formats", "timezone", "contact", "notify"];
+
+    fn same(a: &Option<String>, b: &Option<String>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+            _ => false,
+        }
+    }
+
+    impl Profile {
+        pub fn is_empty(&self) -> bool {
+            *self == Profile::default()
+        }
+
+        // Sets a field from text like "formats Modern, Commander". An empty value clears it.
+        pub fn set(&mut self, field: &str, value: &str) -> Result<(), String> {
+            let value = value.trim();
+            if value.chars().count() > MAX_FIELD_LENGTH {
+                return Err(format!("Profile fields can be at most {} characters long.", MAX_FIELD_LENGTH));
+            }
+            let text = Some(value.to_string()).filter(|v| !v.is_empty());
+            match field.to_lowercase().as_str() {
+                "region" | "country" => self.region = text,
+                "city" => self.city = text,
+                "method" | "methods" | "trades" => {
+                    self.method = match text {
+                        Some(t) => Some(t.parse().map_err(|_| format!("'{}' isn't a way to trade. Try ship, in-person or both.", t))?),
+                        None => None,
+                    }
+                }
+                "store" | "lgs" => self.store = text,
+                "formats" | "format" => {
+                    self.formats = value.split(',').map(|f| f.trim().to_string()).filter(|f| !f.is_empty()).collect();
+                }
+                "timezone" | "tz" => self.timezone = text,
+                "contact" | "notes" => self.contact = text,
+                "notify" | "notifications" => {
+                    self.notify = match text.as_deref().map(str::to_lowercase).as_deref() {
+                        Some("on") | Some("yes") | Some("true") => Some(true),
+                        Some("off") | Some("no") | Some("false") => Some(false),
+                        Some(t) => return Err(format!("'{}' isn't on or off.", t)),
+                        None => None,
+                    }
+                }
+                _ => return Err(format!("'{}' isn't part of a profile. Try {}.", field, FIELDS.join(", "))),
+            }
+            Ok(())
+        }
+
+        fn ships(&self) -> bool {
+            self.method != Some(Method::InPerson)
+        }
+
+        // Whether two traders can trade at all. Someone who only trades in person can't
+        // trade with someone in another region. Unknown details never rule anyone out.
+        pub fn can_trade_with(&self, other: &Profile) -> bool {
+            if self.ships() && other.ships() {
+                return true;
+            }
+            match (&self.region, &other.region) {
+                (Some(_), Some(_)) => same(&self.region, &other.region),
+                _ => true,
+            }
+        }
+
+        // How convenient a trade with the other trader would be, for ranking partners. The
+        // same store beats the same city, which beats the same region.
+        pub fn closeness(&self, other: &Profile) -> u8 {
+            let mut digest = 0;
+            if same(&self.region, &other.region) {
+                digest += 1;
+            }
+            if same(&self.city, &other.city) {
+                digest += 2;
+            }
+            if same(&self.store, &other.store) {
+                digest += 4;
+            }
+            digest
+        }
+
+        // Whether this trader knows enough about themselves to search within the scope.
+        pub fn has_scope(&self, scope: Scope) -> bool {
+            match scope {
+                Scope::Store => self.store.is_some(),
+                Scope::City => self.city.is_some(),
+                Scope::Region => self.region.is_some(),
+                Scope::Ship => true,
+            }
+        }
+
+        // Whether the other trader is within the scope of this one. Partners that haven't
+        // said where they are can't be placed, so only the ship scope includes them.
+        pub fn within(&self, other: &Profile, scope: Scope) -> bool {
+            match scope {
+                Scope::Store => same(&self.store, &other.store),
+                Scope::City => same(&self.city, &other.city),
+                Scope::Region => same(&self.region, &other.region),
+                Scope::Ship => self.can_trade_with(other),
+            }
+        }
+
+        // A short description for matches, like "Portland, US, ships".
+        pub fn summary(&self) -> String {
+            let mut parts: Vec<String> = Vec::new();
+            parts.extend(self.city.clone());
+            parts.extend(self.region.clone());
+            parts.extend(self.method.map(|m| m.to_string()));
+            parts.join(", ")
+        }
+    }
+
+    impl fmt::Display for Profile {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let none = String::from("not given");
+            let show = |v: &Option<String>| v.clone().unwrap_or_else(|| none.clone());
+            writeln!(f, "Region: {}", show(&self.region))?;
+            writeln!(f, "City: {}", show(&self.city))?;
+            writeln!(f, "Trades: {}", self.method.map_or(none.clone(), |m| m.to_string()))?;
+            writeln!(f, "Local game store: {}", show(&self.store))?;
+            writeln!(f, "Formats: {}", if self.formats.is_empty() { none.clone() } else { self.formats.join(", ") })?;
+            writeln!(f, "Timezone: {}", show(&self.timezone))?;
+            writeln!(f, "Contact: {}", show(&self.contact))?;
+            write!(f, "Notifications: {}", if self.notify == Some(false) { "off" } else { "on" })
+        }
+    }
+}
@@ -0,0 +1,403 @@
+pub mod Proposal {
+
+    use std::fmt;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use serde::{Deserialize, Serialize};
+    use serenity::model::id::UserId;
+
+    use crate::card_db::CardDB;
+    use crate::card_entry::CardEntry;
+    use crate::reputation::Reputation;
+
+    // Open proposals expire after a week, and accepted ones a week after they were accepted.
+    pub const PROPOSAL_LIFETIME: u64 = 7 * 24 * 60 * 60;
+
+    // Proposals start out open. Only open proposals can change, and each change is final:
+    // the recipient accepts, declines or counters (which opens a new proposal going the
+    // other way), and the proposer can withdraw it. Once both users confirm that an
+    // accepted trade happened, it is completed and their lists are updated. Until then,
+    // either of them can cancel it.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum Status {
+        Open,
+        Accepted,
+        Completed,
+        Declined,
+        Countered(u64),
+        Withdrawn,
+        Cancelled,
+        Expired,
+    }
+
+    impl fmt::Display for Status {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Status::Open => write!(f, "open"),
+                Status::Accepted => write!(f, "accepted"),
+                Status::Completed => write!(f, "completed"),
+                Status::Declined => write!(f, "declined"),
+                Status::Countered(id) => write!(f, "countered by #{}", id),
+                Status::Withdrawn => write!(f, "withdrawn"),
+                Status::Cancelled => write!(f, "cancelled"),
+                Status::Expired => write!(f, "expired"),
+            }
+        }
+    }
+
+    // An offer from one user to another: the proposer gives one set of cards and gets
+    // another. Times are in seconds since the Unix epoch.
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct Proposal {
+        pub id: u64,
+        pub from: UserId,
+        pub to: UserId,
+        pub give: Vec<CardEntry::CardEntry>,
+        pub get: Vec<CardEntry::CardEntry>,
+        status: Status,
+        pub created: u64,
+        pub expires: u64,
+        // The proposal that this one counters, if any
+        #[serde(default)]
+        pub counters: Option<u64>,
+        // Who has confirmed that the trade happened, and who wants the cards that they
+        // received added to their collection
+        #[serde(default)]
+        confirmed: Vec<UserId>,
+        #[serde(default)]
+        collect: Vec<UserId>,
+        // When the trade was completed, and what each side was worth at the time going by
+        // the price list
+        #[serde(default)]
+        pub completed: Option<u64>,
+        #[serde(default)]
+        pub give_value: Option<f64>,
+        #[serde(default)]
+        pub get_value: Option<f64>,
+        // What each side said the cards that they give are worth, if they said
+        #[serde(default)]
+        pub give_declared: Option<f64>,
+        #[serde(default)]
+        pub get_declared: Option<f64>,
+        // What each side thought of the other, once the trade is completed
+        #[serde(default)]
+        feedback: Vec<Reputation::Feedback>,
+    }
+
+    pub fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+    }
+
+    // The id is given by the store when the proposal is saved.
+    pub fn new(from: UserId, to: UserId, give: Vec<CardEntry::CardEntry>, get: Vec<CardEntry::CardEntry>, counters: Option<u64>) -> Proposal {
+        let created = now();
+        Proposal { id: 0, from, to, give, get, status: Status::Open, created, expires: created + PROPOSAL_LIFETIME, counters, confirmed: Vec::new(), collect: Vec::new(), completed: None, give_value: None, get_value: None, give_declared: None, get_declared: None, feedback: Vec::new() }
+    }
+
+    // Splits an offer like "give: 2 Lightning Bolt; 1 Counterspell get: 4 Brainstorm" into the
+    // cards given and the cards asked for. Cards are separated by semicolons or new lines,
+    // since card names can contain commas. Either side can be left out, but not both.
+    pub fn split_offer(text: &str) -> Result<(String, String), String> {
+        let lower = text.to_lowercase();
+        let give = lower.find("give:");
+        let get = lower.find("get:");
+        let section = |start: Option<usize>, label: usize, other: Option<usize>| -> String {
+            match start {
+                Some(s) => {
+                    let end = other.filter(|o| *o > s).unwrap_or(text.len());
+                    text[s + label..end].replace(';', "\n").trim().to_string()
+                }
+                None => String::new(),
+            }
+        };
+        if give.is_none() && get.is_none() {
+            return Err(String::from("An offer needs 'give:' and/or 'get:' followed by cards, e.g. 'give: 2 Lightning Bolt get: 1 Counterspell'."));
+        }
+        Ok((section(give, 5, get), section(get, 4, give)))
+    }
+
+    // Takes a declared value like "value: 12.50" or "value: $12.50" out of the text. Returns
+    // the rest of the text and the value, if one was given.
+    pub fn split_value(text: &str) -> Result<(String, Option<f64>), String> {
+        let start = match text.to_lowercase().find("value:") {
+            Some(s) => s,
+            None => return Ok((text.to_string(), None)),
+        };
+        let after = &text[start + 6..];
+        let token = after.split_whitespace().next().unwrap_or("");
+        let value = match token.trim_start_matches('$').parse::<f64>() {
+            Ok(v) if v.is_finite() && v >= 0.0 => v,
+            _ => return Err(format!("'{}' isn't a value. Declare what your side of the trade is worth like 'value: 12.50'.", token)),
+        };
+        let end = after.find(token).map_or(0, |i| i + token.len());
+        Ok((format!("{} {}", &text[..start], &after[end..]), Some(value)))
+    }
+
+    // The value of the cards by the price list, if any of them have a price.
+    pub fn value(db: &CardDB::CardDB, cards: &[CardEntry::CardEntry]) -> Option<f64> {
+        let prices: Vec<f64> = cards
+            .iter()
+            .filter_map(|c| db.price(&c.card.get_name(), &c.card.printing).map(|p| p * c.count as f64))
+            .collect();
+        if prices.is_empty() {
+            None
+        } else {
+            Some(prices.iter().sum())
+        }
+    }
+
+    pub fn describe_cards(cards: &[CardEntry::CardEntry]) -> String {
+        if cards.is_empty() {
+            return String::from("nothing");
+        }
+        cards.iter().map(|c| c.to_string()).collect::<Vec<String>>().join(", ")
+    }
+
+    impl Proposal {
+        // Open and accepted proposals past their expiry count as expired even before anyone
+        // looks at them.
+        pub fn status(&self) -> Status {
+            if matches!(self.status, Status::Open | Status::Accepted) && now() >= self.expires {
+                Status::Expired
+            } else {
+                self.status
+            }
+        }
+
+        // True for proposals past their expiry that haven't been marked as expired yet.
+        pub fn needs_expiring(&self) -> bool {
+            self.status != Status::Expired && self.status() == Status::Expired
+        }
+
+        // Marks the proposal as expired if it needs to be. Returns the status that it
+        // expired from, in which case the cards that it held should be let go.
+        pub fn expire(&mut self) -> Option<Status> {
+            if !self.needs_expiring() {
+                return None;
+            }
+            let was = self.status;
+            self.status = Status::Expired;
+            Some(was)
+        }
+
+        pub fn is_open(&self) -> bool {
+            self.status() == Status::Open
+        }
+
+        // Open proposals hold the proposer's cards, and accepted ones hold both sides.
+        pub fn holds_cards(&self) -> bool {
+            matches!(self.status(), Status::Open | Status::Accepted)
+        }
+
+        pub fn involves(&self, user: UserId) -> bool {
+            self.from == user || self.to == user
+        }
+
+        // Moves an open proposal to its next status. Only the recipient can accept, decline
+        // or counter, and only the proposer can withdraw.
+        pub fn close(&mut self, user: UserId, status: Status) -> Result<(), String> {
+            let current = self.status();
+            if current == Status::Accepted && self.involves(user) {
+                return Err(format!("Proposal #{} was already accepted. Use '!trade cancel {}' to call it off.", self.id, self.id));
+            }
+            if current != Status::Open {
+                return Err(format!("Proposal #{} is {}.", self.id, current));
+            }
+            let allowed = match status {
+                Status::Withdrawn => user == self.from,
+                Status::Open | Status::Completed | Status::Cancelled | Status::Expired => false,
+                _ => user == self.to,
+            };
+            if !allowed {
+                return Err(match status {
+                    Status::Withdrawn => format!("Only <@{}> can withdraw proposal #{}.", self.from, self.id),
+                    _ => format!("Only <@{}> can respond to proposal #{}.", self.to, self.id),
+                });
+            }
+            self.status = status;
+            if status == Status::Accepted {
+                // The users get a fresh week to meet up and complete the trade
+                self.expires = now() + PROPOSAL_LIFETIME;
+            }
+            Ok(())
+        }
+
+        // Calls off an accepted trade that hasn't been completed. Either user can.
+        pub fn cancel(&mut self, user: UserId) -> Result<(), String> {
+            if !self.involves(user) {
+                return Err(format!("There is no proposal #{} for you.", self.id));
+            }
+            match self.status() {
+                Status::Accepted => {}
+                Status::Open => return Err(format!("Proposal #{} hasn't been accepted yet. Decline or withdraw it instead.", self.id)),
+                status => return Err(format!("Proposal #{} is {}.", self.id, status)),
+            }
+            self.status = Status::Cancelled;
+            Ok(())
+        }
+
+        // Records that the user has confirmed the accepted trade. Returns true once both
+        // users have, at which point the trade should be completed.
+        pub fn confirm(&mut self, user: UserId, collect: bool) -> Result<bool, String> {
+            if !self.involves(user) {
+                return Err(format!("There is no proposal #{} for you.", self.id));
+            }
+            match self.status() {
+                Status::Accepted => {}
+                Status::Open => return Err(format!("Proposal #{} hasn't been accepted yet.", self.id)),
+                status => return Err(format!("Proposal #{} is {}.", self.id, status)),
+            }
+            if self.confirmed.contains(&user) {
+                let other = if user == self.from { self.to } else { self.from };
+                return Err(format!("You already confirmed proposal #{}. It completes once <@{}> confirms it too.", self.id, other));
+            }
+            self.confirmed.push(user);
+            if collect {
+                self.collect.push(user);
+            }
+            Ok(self.confirmed.len() == 2)
+        }
+
+        pub fn complete(&mut self, db: &CardDB::CardDB) {
+            self.status = Status::Completed;
+            self.completed = Some(now());
+            self.give_value = value(db, &self.give);
+            self.get_value = value(db, &self.get);
+        }
+
+        // Records what the user says the cards that they give are worth.
+        pub fn declare(&mut self, user: UserId, value: f64) {
+            if user == self.from {
+                self.give_declared = Some(value);
+            } else if user == self.to {
+                self.get_declared = Some(value);
+            }
+        }
+
+        pub fn feedback(&self) -> &[Reputation::Feedback] {
+            &self.feedback
+        }
+
+        // Each side of a completed trade can leave feedback about the other side once.
+        pub fn leave_feedback(&mut self, feedback: Reputation::Feedback) -> Result<(), String> {
+            if !self.involves(feedback.from) {
+                return Err(format!("You weren't part of trade #{}.", self.id));
+            }
+            if self.status() != Status::Completed {
+                return Err(format!("Feedback can only be left on completed trades, and trade #{} is {}.", self.id, self.status()));
+            }
+            if self.feedback.iter().any(|f| f.from == feedback.from) {
+                return Err(format!("You already left feedback on trade #{}.", self.id));
+            }
+            self.feedback.push(feedback);
+            Ok(())
+        }
+
+        pub fn collects(&self, user: UserId) -> bool {
+            self.collect.contains(&user)
+        }
+
+        // A summary of a completed trade, given the wants that it filled on each side.
+        pub fn receipt(&self, from_filled: &[CardEntry::CardEntry], to_filled: &[CardEntry::CardEntry]) -> String {
+            let mut digest = format!("Trade #{} is complete.", self.id);
+            for (user, gave, filled) in [(self.from, &self.give, from_filled), (self.to, &self.get, to_filled)] {
+                digest += &format!("\n<@{}> gave {}.", user, describe_cards(gave));
+                if !filled.is_empty() {
+                    digest += &format!(" <@{}>'s wants for {} were filled.", user, describe_cards(filled));
+                }
+                if self.collects(user) {
+                    digest += &format!(" <@{}>'s new cards were added to their collection.", user);
+                }
+            }
+            digest
+        }
+    }
+
+    impl fmt::Display for Proposal {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "#{}: <@{}> gives {} to <@{}> for {} ({})",
+                self.id,
+                self.from,
+                describe_cards(&self.give),
+                self.to,
+                describe_cards(&self.get),
+                self.status()
+            )
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const FROM: UserId = UserId(1);
+        const TO: UserId = UserId(2);
+
+        fn accepted() -> Proposal {
+            let mut proposal = new(FROM, TO, Vec::new(), Vec::new(), None);
+            proposal.close(TO, Status::Accepted).unwrap();
+            proposal
+        }
+
+        #[test]
+        fn accepted_proposal_completes_once_both_confirm() {
+            let mut proposal = accepted();
+            assert!(proposal.holds_cards());
+            assert_eq!(proposal.confirm(FROM, true), Ok(false));
+            assert!(proposal.confirm(FROM, false).is_err());
+            assert_eq!(proposal.confirm(TO, false), Ok(true));
+            proposal.complete(&CardDB::new());
+            assert_eq!(proposal.status(), Status::Completed);
+            assert!(proposal.completed.is_some());
+            assert!(proposal.collects(FROM));
+            assert!(!proposal.collects(TO));
+            assert!(!proposal.holds_cards());
+        }
+
+        #[test]
+        fn accepted_proposal_can_be_cancelled_by_either_side() {
+            for user in [FROM, TO] {
+                let mut proposal = accepted();
+                assert!(proposal.close(user, Status::Declined).is_err());
+                assert_eq!(proposal.cancel(user), Ok(()));
+                assert_eq!(proposal.status(), Status::Cancelled);
+                assert!(!proposal.holds_cards());
+                assert!(proposal.confirm(user, false).is_err());
+            }
+            assert!(accepted().cancel(UserId(3)).is_err());
+            assert!(new(FROM, TO, Vec::new(), Vec::new(), None).cancel(FROM).is_err());
+        }
+
+        #[test]
+        fn declared_value_is_taken_out_of_the_offer() {
+            let (rest, value) = split_value("give: 2 Lightning Bolt value: $12.50 get: 1 Counterspell").unwrap();
+            assert_eq!(value, Some(12.5));
+            assert_eq!(split_offer(&rest), Ok((String::from("2 Lightning Bolt"), String::from("1 Counterspell"))));
+            assert_eq!(split_value("12 Value: 3").unwrap().1, Some(3.0));
+            assert_eq!(split_value("give: 1 Duress").unwrap(), (String::from("give: 1 Duress"), None));
+            assert!(split_value("give: 1 Duress value: lots").is_err());
+            assert!(split_value("give: 1 Duress value: -5").is_err());
+        }
+
+        #[test]
+        fn each_side_declares_what_it_gives() {
+            let mut proposal = accepted();
+            proposal.declare(FROM, 10.0);
+            proposal.declare(TO, 8.0);
+            proposal.declare(UserId(3), 1.0);
+            assert_eq!((proposal.give_declared, proposal.get_declared), (Some(10.0), Some(8.0)));
+        }
+
+        #[test]
+        fn accepted_proposal_expires() {
+            let mut proposal = accepted();
+            proposal.expires = now() - 1;
+            assert_eq!(proposal.status(), Status::Expired);
+            assert_eq!(proposal.expire(), Some(Status::Accepted));
+            assert_eq!(proposal.expire(), None);
+            assert!(proposal.cancel(FROM).is_err());
+        }
+    }
+}
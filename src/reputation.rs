@@ -0,0 +1,136 @@
+pub mod Reputation {
+
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::str::FromStr;
+
+    use serde::{Deserialize, Serialize};
+    use serenity::model::id::UserId;
+
+    use crate::proposal::Proposal;
+
+    // Comments are shown in embeds, so they are kept short.
+    pub const MAX_COMMENT_LENGTH: usize = 200;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum Rating {
+        Positive,
+        Neutral,
+        Negative,
+    }
+
+    impl FromStr for Rating {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.to_lowercase().as_str() {
+                "+" | "+1" | "positive" | "pos" | "good" => Ok(Rating::Positive),
+                "0" | "neutral" | "neu" | "ok" => Ok(Rating::Neutral),
+                "-" | "-1" | "negative" | "neg" | "bad" => Ok(Rating::Negative),
+                _ => Err(()),
+            }
+        }
+    }
+
+    impl fmt::Display for Rating {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let name = match self {
+                Rating::Positive => "positive",
+                Rating::Neutral => "neutral",
+                Rating::Negative => "negative",
+            };
+            write!(f, "{}", name)
+        }
+    }
+
+    // What one side of a completed trade thought of the other side.
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct Feedback {
+        pub from: UserId,
+        pub rating: Rating,
+        pub comment: Option<String>,
+        pub time: u64,
+    }
+
+    pub fn new(from: UserId, rating: Rating, comment: &str) -> Result<Feedback, String> {
+        let comment = comment.trim();
+        if comment.chars().count() > MAX_COMMENT_LENGTH {
+            return Err(format!("Feedback comments can be at most {} characters long.", MAX_COMMENT_LENGTH));
+        }
+        let comment = Some(comment.to_string()).filter(|c| !c.is_empty());
+        Ok(Feedback { from, rating, comment, time: Proposal::now() })
+    }
+
+    // The feedback a user has received across their completed trades.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct Reputation {
+        pub positive: u32,
+        pub neutral: u32,
+        pub negative: u32,
+    }
+
+    impl Reputation {
+        fn add(&mut self, rating: Rating) {
+            match rating {
+                Rating::Positive => self.positive += 1,
+                Rating::Neutral => self.neutral += 1,
+                Rating::Negative => self.negative += 1,
+            }
+        }
+
+        // Positive feedback counts for and negative feedback against. Neutral feedback is
+        // shown but doesn't count.
+        pub fn score(&self) -> i64 {
+            self.positive as i64 - self.negative as i64
+        }
+
+        pub fn total(&self) -> u32 {
+            self.positive + self.neutral + self.negative
+        }
+    }
+
+    impl fmt::Display for Reputation {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            if self.total() == 0 {
+                return write!(f, "no feedback yet");
+            }
+            write!(f, "{:+} ({} positive, {} neutral, {} negative)", self.score(), self.positive, self.neutral, self.negative)
+        }
+    }
+
+    // The feedback that the user received, newest first, with the trade it was left on.
+    pub fn received(user: UserId, proposals: &[Proposal::Proposal]) -> Vec<(u64, Feedback)> {
+        let mut digest: Vec<(u64, Feedback)> = proposals
+            .iter()
+            .filter(|p| p.involves(user))
+            .flat_map(|p| p.feedback().iter().filter(|f| f.from != user).map(move |f| (p.id, f.clone())))
+            .collect();
+        digest.sort_by_key(|(_, f)| std::cmp::Reverse(f.time));
+        digest
+    }
+
+    pub fn of(user: UserId, proposals: &[Proposal::Proposal]) -> Reputation {
+        let mut digest = Reputation::default();
+        for (_, feedback) in received(user, proposals) {
+            digest.add(feedback.rating);
+        }
+        digest
+    }
+
+    // Everyone's reputation at once, for commands that show many traders.
+    pub fn all(proposals: &[Proposal::Proposal]) -> HashMap<UserId, Reputation> {
+        let mut digest: HashMap<UserId, Reputation> = HashMap::new();
+        for p in proposals {
+            for feedback in p.feedback() {
+                let about = if feedback.from == p.from { p.to } else { p.from };
+                digest.entry(about).or_default().add(feedback.rating);
+            }
+        }
+        digest
+    }
+
+    // Reads a minimum reputation option like "rep:3" or "rep:-1".
+    pub fn parse_minimum(token: &str) -> Option<i64> {
+        token.strip_prefix("rep:")?.parse().ok()
+    }
+}
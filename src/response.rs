@@ -1,14 +1,13 @@
 pub mod Response {
 
-    use std::collections::HashMap;
-
-    use serenity::builder::CreateMessage;
+    use serenity::builder::CreateEmbed;
     use serenity::framework::standard::CommandResult;
-    use serenity::model::channel::Embed;
-    use serenity::model::channel::Message;
+    use serenity::model::channel::{Message, ReactionType};
     use serenity::prelude::*;
     use serenity::utils::Colour;
 
+    use crate::pager::Pager;
+
     // Models a serenity Embed.  Unlike in discord.py, embeds can't really be
     // created, modified, and then sent.  The send_message method takes a
     // FnOnce of a CreateEmbed, so they have to be created when they are sent.
@@ -26,57 +25,88 @@ pub mod Response {
     pub struct Response {
         content: Option<String>,
         embed: Option<EmbedSpoof>,
+        // Attached files as (filename, contents)
+        files: Vec<(String, Vec<u8>)>,
+        // Further pages of the embed, which the user can turn to with reactions
+        pages: Vec<EmbedSpoof>,
     }
 
     pub fn new() -> Response {
         Response {
             content: None,
             embed: None,
+            files: Vec::new(),
+            pages: Vec::new(),
         }
     }
 
-    pub async fn send_message(res: Response, ctx: &Context, msg: &Message) -> CommandResult {
-        if (res.content.is_some() && res.embed.is_some()) {
-            // The message contains both content and an embed
-            let embed = res.embed.unwrap().clone();
-            msg.channel_id
-                .send_message(&ctx.http, |m| {
-                    m.embed(|mut e| {
-                        e.title(embed.title);
-                        e.colour(embed.colour);
-                        e.fields(embed.fields);
+    pub fn build_embed(e: &mut CreateEmbed, embed: EmbedSpoof) -> &mut CreateEmbed {
+        e.title(embed.title);
+        e.colour(embed.colour);
+        e.fields(embed.fields);
+        e
+    }
 
-                        e
-                    });
+    // Discord rejects messages with more than 2000 characters of content.
+    const MAX_CONTENT_LENGTH: usize = 2000;
 
-                    m
-                })
-                .await?;
-            Ok(())
-        } else if (res.content.is_some()) {
-            // The response only contains some content
-            msg.channel_id.say(&ctx.http, &res.content.unwrap()).await?;
-            Ok(())
-        } else if (res.embed.is_some()) {
-            // The response only contains an embed
-            let embed = res.embed.unwrap().clone();
-            msg.channel_id
-                .send_message(&ctx.http, |m| {
-                    m.embed(|mut e| {
-                        e.title(embed.title);
-                        e.colour(embed.colour);
-                        e.fields(embed.fields);
-
-                        e
-                    });
+    fn truncate(content: String) -> String {
+        if content.chars().count() <= MAX_CONTENT_LENGTH {
+            return content;
+        }
+        let mut digest: String = content.chars().take(MAX_CONTENT_LENGTH - 4).collect();
+        digest.push_str("\n...");
+        digest
+    }
 
+    pub async fn send_message(res: Response, ctx: &Context, msg: &Message) -> CommandResult {
+        if !res.files.is_empty() {
+            // Files can only be sent alongside content, not embeds
+            let files: Vec<(&[u8], &str)> = res
+                .files
+                .iter()
+                .map(|(name, data)| (data.as_slice(), name.as_str()))
+                .collect();
+            let content = res.content.map(truncate).unwrap_or_default();
+            msg.channel_id
+                .send_files(&ctx.http, files, |m| {
+                    m.content(content);
                     m
                 })
                 .await?;
-            Ok(())
-        } else {
+            return Ok(());
+        }
+        match (res.content.map(truncate), res.embed) {
+            (content, Some(embed)) => {
+                // The message contains an embed and possibly some content
+                let sent = msg.channel_id
+                    .send_message(&ctx.http, |m| {
+                        if let Some(c) = content {
+                            m.content(c);
+                        }
+                        m.embed(|e| build_embed(e, embed));
+
+                        m
+                    })
+                    .await?;
+                if res.pages.len() > 1 {
+                    for arrow in [Pager::PREVIOUS, Pager::NEXT] {
+                        sent.react(&ctx.http, ReactionType::Unicode(arrow.to_string())).await?;
+                    }
+                    let data = ctx.data.read().await;
+                    if let Some(paginated) = data.get::<Pager::Paginated>() {
+                        Pager::track(paginated, sent.id, msg.author.id, res.pages);
+                    }
+                }
+                Ok(())
+            }
+            (Some(content), None) => {
+                // The response only contains some content
+                msg.channel_id.say(&ctx.http, &content).await?;
+                Ok(())
+            }
             // There is neither content nor an embed, so we will exit quietly
-            Ok(())
+            (None, None) => Ok(()),
         }
     }
 
@@ -100,5 +130,15 @@ pub mod Response {
         pub fn set_embed(&mut self, embed: EmbedSpoof) {
             self.embed = Some(embed);
         }
+
+        // Shows the first page, with reactions to turn to the others.
+        pub fn set_pages(&mut self, pages: Vec<EmbedSpoof>) {
+            self.embed = pages.first().cloned();
+            self.pages = pages;
+        }
+
+        pub fn add_file(&mut self, filename: String, data: Vec<u8>) {
+            self.files.push((filename, data));
+        }
     }
 }
@@ -0,0 +1,138 @@
+pub mod Session {
+
+    use std::fmt;
+
+    use dashmap::DashMap;
+    use serenity::model::id::UserId;
+    use serenity::prelude::*;
+
+    use crate::profile::Profile;
+    use crate::proposal::Proposal;
+
+    // Sessions last for an evening unless the trader says otherwise, and never for more than
+    // half a day.
+    pub const DEFAULT_DURATION: u64 = 3 * 60 * 60;
+    pub const MAX_DURATION: u64 = 12 * 60 * 60;
+
+    // A trader who is looking for trades at a store right now. Times are in seconds since
+    // the Unix epoch.
+    #[derive(Clone, Debug)]
+    pub struct Session {
+        pub store: String,
+        pub started: u64,
+        pub ends: u64,
+    }
+
+    // Store names are shown as embed field names, so they are held to the same length as
+    // the store in a profile.
+    pub fn new(store: String, duration: u64) -> Result<Session, String> {
+        if duration > MAX_DURATION {
+            return Err(format!("Sessions can last at most {} hours.", MAX_DURATION / (60 * 60)));
+        }
+        if store.chars().count() > Profile::MAX_FIELD_LENGTH {
+            return Err(format!("Store names can be at most {} characters long.", Profile::MAX_FIELD_LENGTH));
+        }
+        let started = Proposal::now();
+        Ok(Session { store, started, ends: started + duration })
+    }
+
+    // Sessions only matter while they last, so they are kept in memory rather than stored.
+    pub struct Sessions;
+
+    impl TypeMapKey for Sessions {
+        type Value = DashMap<UserId, Session>;
+    }
+
+    // Reads a duration like "2h", "45m" or "1h30m". Every number needs a unit, so that a
+    // store name ending in a number isn't taken for a duration. Durations too long to count
+    // are None.
+    pub fn parse_duration(text: &str) -> Option<u64> {
+        let mut digest: u64 = 0;
+        let mut number = String::new();
+        for c in text.trim().to_lowercase().chars() {
+            match c {
+                '0'..='9' => number.push(c),
+                'h' | 'm' if !number.is_empty() => {
+                    let unit = if c == 'h' { 60 * 60 } else { 60 };
+                    let seconds = number.parse::<u64>().ok()?.checked_mul(unit)?;
+                    digest = digest.checked_add(seconds)?;
+                    number.clear();
+                }
+                _ => return None,
+            }
+        }
+        if number.is_empty() && digest > 0 {
+            Some(digest)
+        } else {
+            None
+        }
+    }
+
+    // Starts or replaces the user's session, letting go of any that have ended.
+    pub fn start(sessions: &DashMap<UserId, Session>, user: UserId, session: Session) {
+        sessions.retain(|_, s| s.is_active());
+        sessions.insert(user, session);
+    }
+
+    // Ends the user's session early. Returns false if they had none.
+    pub fn stop(sessions: &DashMap<UserId, Session>, user: UserId) -> bool {
+        sessions.remove(&user).map_or(false, |(_, s)| s.is_active())
+    }
+
+    pub fn get(sessions: &DashMap<UserId, Session>, user: UserId) -> Option<Session> {
+        sessions.get(&user).map(|s| s.clone()).filter(|s| s.is_active())
+    }
+
+    // Everyone trading at the store right now.
+    pub fn at(sessions: &DashMap<UserId, Session>, store: &str) -> Vec<(UserId, Session)> {
+        sessions.retain(|_, s| s.is_active());
+        sessions
+            .iter()
+            .filter(|s| s.store.eq_ignore_ascii_case(store))
+            .map(|s| (*s.key(), s.value().clone()))
+            .collect()
+    }
+
+    impl Session {
+        pub fn is_active(&self) -> bool {
+            Proposal::now() < self.ends
+        }
+    }
+
+    impl fmt::Display for Session {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "at {} until <t:{}:t>", self.store, self.ends)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn durations_need_units() {
+            assert_eq!(parse_duration("2h"), Some(2 * 60 * 60));
+            assert_eq!(parse_duration("45m"), Some(45 * 60));
+            assert_eq!(parse_duration("1h30m"), Some(90 * 60));
+            assert_eq!(parse_duration("90"), None);
+            assert_eq!(parse_duration("1h30"), None);
+            assert_eq!(parse_duration("0m"), None);
+            assert_eq!(parse_duration("Store 2"), None);
+        }
+
+        #[test]
+        fn sessions_stay_within_limits() {
+            let session = new(String::from("Dragon's Den"), MAX_DURATION).unwrap();
+            assert_eq!(session.ends - session.started, MAX_DURATION);
+            assert!(new(String::from("Dragon's Den"), MAX_DURATION + 60).is_err());
+            assert!(new("x".repeat(Profile::MAX_FIELD_LENGTH + 1), DEFAULT_DURATION).is_err());
+        }
+
+        #[test]
+        fn durations_that_overflow_are_rejected() {
+            assert_eq!(parse_duration("99999999999999999999h"), None);
+            assert_eq!(parse_duration(&format!("{}h", u64::MAX / 60)), None);
+            assert_eq!(parse_duration(&format!("{}m{}m", u64::MAX / 60, u64::MAX / 60)), None);
+        }
+    }
+}
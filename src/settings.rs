@@ -0,0 +1,19 @@
+pub mod Settings {
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::profile::Profile;
+
+    // What a guild's moderators have chosen for the bot in their server. Settings that
+    // aren't set fall back to the bot's own defaults.
+    #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct Settings {
+        // How far matches and searches look for partners unless told otherwise
+        pub scope: Option<Profile::Scope>,
+    }
+
+    pub fn new() -> Settings {
+        Settings::default()
+    }
+}
@@ -0,0 +1,545 @@
+pub mod SqliteStore {
+
+    use std::collections::BTreeMap;
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+    use serenity::model::id::{GuildId, UserId};
+
+    use crate::card_entry::CardEntry;
+    use crate::migrations::Migrations;
+    use crate::profile::Profile;
+    use crate::proposal::Proposal;
+    use crate::settings::Settings;
+    use crate::trade_store::TradeStore::{Contents, StoreResult, TradeStore};
+    use crate::trader::Trader;
+    use crate::tradelist::Tradelist;
+
+    // Keeps traders and their lists in a SQLite database. Each entry is its own row, indexed
+    // by card name, so searches for a card don't have to read every list.
+    pub struct SqliteStore {
+        conn: Mutex<Connection>,
+    }
+
+    // The version of the database that this build uses. It is kept in SQLite's user_version.
+    pub const DATABASE_VERSION: u32 = 6;
+
+    // Step i upgrades the database from version i to version i + 1. Each step runs in the
+    // same transaction as the version bump.
+    const MIGRATIONS: [(&str, &str); DATABASE_VERSION as usize] = [
+        ("Create the tradelists and entries tables", SCHEMA_V1),
+        ("Move each tradelist into a trader's list of named lists", SCHEMA_V2),
+        ("Add collections to traders", SCHEMA_V3),
+        ("Create the proposals table", SCHEMA_V4),
+        ("Add profiles to traders", SCHEMA_V5),
+        ("Create the guilds table", SCHEMA_V6),
+    ];
+
+    // Databases made before versioning already have these tables, hence IF NOT EXISTS.
+    const SCHEMA_V1: &str = "
+        CREATE TABLE IF NOT EXISTS tradelists (
+            user_id INTEGER PRIMARY KEY,
+            is_public INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS entries (
+            user_id INTEGER NOT NULL REFERENCES tradelists(user_id) ON DELETE CASCADE,
+            kind TEXT NOT NULL,
+            name TEXT NOT NULL,
+            data TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS entries_by_name ON entries (kind, name);
+        CREATE INDEX IF NOT EXISTS entries_by_user ON entries (user_id);
+    ";
+
+    // Each tradelist becomes the only list of a trader, named "Main".
+    const SCHEMA_V2: &str = "
+        CREATE TABLE traders (
+            user_id INTEGER PRIMARY KEY,
+            default_list TEXT NOT NULL
+        );
+        CREATE TABLE lists (
+            user_id INTEGER NOT NULL REFERENCES traders(user_id) ON DELETE CASCADE,
+            name TEXT NOT NULL,
+            is_public INTEGER NOT NULL,
+            PRIMARY KEY (user_id, name)
+        );
+        CREATE TABLE list_entries (
+            user_id INTEGER NOT NULL,
+            list TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            name TEXT NOT NULL,
+            data TEXT NOT NULL,
+            FOREIGN KEY (user_id, list) REFERENCES lists(user_id, name) ON DELETE CASCADE
+        );
+        INSERT INTO traders (user_id, default_list) SELECT user_id, 'Main' FROM tradelists;
+        INSERT INTO lists (user_id, name, is_public) SELECT user_id, 'Main', is_public FROM tradelists;
+        INSERT INTO list_entries (user_id, list, kind, name, data) SELECT user_id, 'Main', kind, name, data FROM entries;
+        DROP TABLE entries;
+        DROP TABLE tradelists;
+        CREATE INDEX list_entries_by_name ON list_entries (kind, name);
+        CREATE INDEX list_entries_by_user ON list_entries (user_id);
+    ";
+
+    // The collection is kept as json, since only its surplus (in list_entries) is searched.
+    const SCHEMA_V3: &str = "
+        ALTER TABLE traders ADD COLUMN collection TEXT NOT NULL DEFAULT '{}';
+    ";
+
+    // Proposals are kept as json, next to the two users so that each can find theirs.
+    const SCHEMA_V4: &str = "
+        CREATE TABLE proposals (
+            id INTEGER PRIMARY KEY,
+            from_user INTEGER NOT NULL,
+            to_user INTEGER NOT NULL,
+            data TEXT NOT NULL
+        );
+        CREATE INDEX proposals_by_from ON proposals (from_user);
+        CREATE INDEX proposals_by_to ON proposals (to_user);
+    ";
+
+    const SCHEMA_V5: &str = "
+        ALTER TABLE traders ADD COLUMN profile TEXT NOT NULL DEFAULT '{}';
+    ";
+
+    const SCHEMA_V6: &str = "
+        CREATE TABLE guilds (
+            guild_id INTEGER PRIMARY KEY,
+            settings TEXT NOT NULL
+        );
+    ";
+
+    const HAVE: &str = "have";
+    const WANT: &str = "want";
+
+    fn to_string<E: std::fmt::Display>(e: E) -> String {
+        e.to_string()
+    }
+
+    fn version(conn: &Connection) -> StoreResult<u32> {
+        conn.query_row("PRAGMA user_version", [], |r| r.get(0)).map_err(to_string)
+    }
+
+    // The migrations that opening the database would apply. Nothing is changed.
+    pub fn pending(path: &Path) -> StoreResult<Vec<String>> {
+        let from = if path.exists() {
+            let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(to_string)?;
+            version(&conn)?
+        } else {
+            0
+        };
+        if from > DATABASE_VERSION {
+            return Err(too_new(from));
+        }
+        Ok(MIGRATIONS
+            .iter()
+            .enumerate()
+            .skip(from as usize)
+            .map(|(i, (name, _))| Migrations::describe(i as u32, name))
+            .collect())
+    }
+
+    fn too_new(version: u32) -> String {
+        format!("the database is version {}, but this build only knows up to version {}", version, DATABASE_VERSION)
+    }
+
+    // Upgrades the database to the current version. A database that already has data is
+    // backed up next to itself first.
+    fn migrate(conn: &mut Connection, path: &Path) -> StoreResult<()> {
+        let from = version(conn)?;
+        if from > DATABASE_VERSION {
+            return Err(too_new(from));
+        }
+        if from == DATABASE_VERSION {
+            return Ok(());
+        }
+        let tables: u32 = conn
+            .query_row("SELECT count(*) FROM sqlite_master WHERE type = 'table'", [], |r| r.get(0))
+            .map_err(to_string)?;
+        if tables > 0 {
+            let backup = Migrations::backup_path(path, from);
+            conn.execute("VACUUM INTO ?1", params![backup.to_string_lossy()]).map_err(to_string)?;
+        }
+        let tx = conn.transaction().map_err(to_string)?;
+        for (i, (name, sql)) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+            tx.execute_batch(sql).map_err(|e| format!("{}: {}", Migrations::describe(i as u32, name), e))?;
+            tx.execute_batch(&format!("PRAGMA user_version = {}", i + 1)).map_err(to_string)?;
+            println!("Migrated {} from {}", path.display(), Migrations::describe(i as u32, name));
+        }
+        tx.commit().map_err(to_string)
+    }
+
+    pub fn open(path: &Path) -> StoreResult<SqliteStore> {
+        let mut conn = Connection::open(path).map_err(to_string)?;
+        // WAL lets searches read while a command is writing
+        conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(())).map_err(to_string)?;
+        conn.execute_batch("PRAGMA foreign_keys = ON;").map_err(to_string)?;
+        migrate(&mut conn, path)?;
+        Ok(SqliteStore { conn: Mutex::new(conn) })
+    }
+
+    fn load_trader(conn: &Connection, user: UserId) -> StoreResult<Option<Trader::Trader>> {
+        let id = user.0 as i64;
+        let trader: Option<(String, String, String)> = conn
+            .query_row(
+                "SELECT default_list, collection, profile FROM traders WHERE user_id = ?1",
+                params![id],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .optional()
+            .map_err(to_string)?;
+        let (default, collection, profile) = match trader {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        let collection = serde_json::from_str(&collection).map_err(to_string)?;
+        let profile = serde_json::from_str(&profile).map_err(to_string)?;
+        let mut lists = BTreeMap::new();
+        let mut stmt = conn
+            .prepare_cached("SELECT name, is_public FROM lists WHERE user_id = ?1")
+            .map_err(to_string)?;
+        let rows = stmt
+            .query_map(params![id], |r| Ok((r.get::<_, String>(0)?, r.get::<_, bool>(1)?)))
+            .map_err(to_string)?;
+        for row in rows {
+            let (name, is_public) = row.map_err(to_string)?;
+            let mut list = Tradelist::new();
+            if is_public {
+                list.set_public();
+            }
+            lists.insert(name, list);
+        }
+        let mut stmt = conn
+            .prepare_cached("SELECT list, kind, data FROM list_entries WHERE user_id = ?1")
+            .map_err(to_string)?;
+        let rows = stmt
+            .query_map(params![id], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?, r.get::<_, String>(2)?)))
+            .map_err(to_string)?;
+        for row in rows {
+            let (name, kind, data) = row.map_err(to_string)?;
+            let entry: CardEntry::CardEntry = serde_json::from_str(&data).map_err(to_string)?;
+            let list = lists.entry(name).or_insert_with(Tradelist::new);
+            if kind == WANT {
+                list.add_want(entry);
+            } else {
+                list.add_card(entry);
+            }
+        }
+        let mut trader = Trader::from_lists(lists, default, collection);
+        *trader.profile_mut() = profile;
+        Ok(Some(trader))
+    }
+
+    fn write_trader(conn: &Connection, user: UserId, trader: &Trader::Trader) -> StoreResult<()> {
+        let id = user.0 as i64;
+        // Deleting the trader cascades to their lists and entries
+        conn.execute("DELETE FROM traders WHERE user_id = ?1", params![id]).map_err(to_string)?;
+        let collection = serde_json::to_string(trader.collection()).map_err(to_string)?;
+        let profile = serde_json::to_string(trader.profile()).map_err(to_string)?;
+        conn.execute(
+            "INSERT INTO traders (user_id, default_list, collection, profile) VALUES (?1, ?2, ?3, ?4)",
+            params![id, trader.default_name(), collection, profile],
+        )
+        .map_err(to_string)?;
+        let mut insert_list = conn
+            .prepare_cached("INSERT INTO lists (user_id, name, is_public) VALUES (?1, ?2, ?3)")
+            .map_err(to_string)?;
+        let mut insert_entry = conn
+            .prepare_cached("INSERT INTO list_entries (user_id, list, kind, name, data) VALUES (?1, ?2, ?3, ?4, ?5)")
+            .map_err(to_string)?;
+        for (name, list) in trader.lists() {
+            insert_list.execute(params![id, name, list.is_public()]).map_err(to_string)?;
+            let entries = list.haves().iter().map(|e| (HAVE, e)).chain(list.wants().iter().map(|e| (WANT, e)));
+            for (kind, entry) in entries {
+                let data = serde_json::to_string(entry).map_err(to_string)?;
+                insert_entry.execute(params![id, name, kind, entry.card.get_name(), data]).map_err(to_string)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn load_proposal(conn: &Connection, id: u64) -> StoreResult<Option<Proposal::Proposal>> {
+        let data: Option<String> = conn
+            .query_row("SELECT data FROM proposals WHERE id = ?1", params![id as i64], |r| r.get(0))
+            .optional()
+            .map_err(to_string)?;
+        match data {
+            Some(d) => serde_json::from_str(&d).map(Some).map_err(to_string),
+            None => Ok(None),
+        }
+    }
+
+    fn write_proposal(conn: &Connection, proposal: &Proposal::Proposal) -> StoreResult<()> {
+        let data = serde_json::to_string(proposal).map_err(to_string)?;
+        conn.execute(
+            "INSERT OR REPLACE INTO proposals (id, from_user, to_user, data) VALUES (?1, ?2, ?3, ?4)",
+            params![proposal.id as i64, proposal.from.0 as i64, proposal.to.0 as i64, data],
+        )
+        .map_err(to_string)?;
+        Ok(())
+    }
+
+    fn query_proposals(conn: &Connection, sql: &str, params: &[&dyn rusqlite::ToSql]) -> StoreResult<Vec<Proposal::Proposal>> {
+        let mut stmt = conn.prepare_cached(sql).map_err(to_string)?;
+        let rows = stmt.query_map(params, |r| r.get::<_, String>(0)).map_err(to_string)?;
+        let mut digest = Vec::new();
+        for row in rows {
+            digest.push(serde_json::from_str(&row.map_err(to_string)?).map_err(to_string)?);
+        }
+        Ok(digest)
+    }
+
+    fn load_traders(conn: &Connection) -> StoreResult<Vec<(UserId, Trader::Trader)>> {
+        let mut stmt = conn.prepare_cached("SELECT user_id FROM traders").map_err(to_string)?;
+        let ids = stmt
+            .query_map([], |r| r.get::<_, i64>(0))
+            .map_err(to_string)?
+            .collect::<Result<Vec<i64>, _>>()
+            .map_err(to_string)?;
+        let mut digest = Vec::new();
+        for id in ids {
+            let user = UserId(id as u64);
+            if let Some(trader) = load_trader(conn, user)? {
+                digest.push((user, trader));
+            }
+        }
+        Ok(digest)
+    }
+
+    fn load_all_settings(conn: &Connection) -> StoreResult<Vec<(GuildId, Settings::Settings)>> {
+        let mut stmt = conn.prepare_cached("SELECT guild_id, settings FROM guilds").map_err(to_string)?;
+        let rows = stmt
+            .query_map([], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?)))
+            .map_err(to_string)?;
+        let mut digest = Vec::new();
+        for row in rows {
+            let (id, data) = row.map_err(to_string)?;
+            digest.push((GuildId(id as u64), serde_json::from_str(&data).map_err(to_string)?));
+        }
+        Ok(digest)
+    }
+
+    fn load_settings(conn: &Connection, guild: GuildId) -> StoreResult<Settings::Settings> {
+        let data: Option<String> = conn
+            .query_row("SELECT settings FROM guilds WHERE guild_id = ?1", params![guild.0 as i64], |r| r.get(0))
+            .optional()
+            .map_err(to_string)?;
+        match data {
+            Some(d) => serde_json::from_str(&d).map_err(to_string),
+            None => Ok(Settings::new()),
+        }
+    }
+
+    fn write_settings(conn: &Connection, guild: GuildId, settings: &Settings::Settings) -> StoreResult<()> {
+        let data = serde_json::to_string(settings).map_err(to_string)?;
+        conn.execute(
+            "INSERT OR REPLACE INTO guilds (guild_id, settings) VALUES (?1, ?2)",
+            params![guild.0 as i64, data],
+        )
+        .map_err(to_string)?;
+        Ok(())
+    }
+
+    impl SqliteStore {
+        // Replaces the user's lists. Used to move traders into a new database.
+        pub fn put(&self, user: UserId, trader: &Trader::Trader) -> StoreResult<()> {
+            let mut conn = self.conn.lock().map_err(to_string)?;
+            let tx = conn.transaction().map_err(to_string)?;
+            write_trader(&tx, user, trader)?;
+            tx.commit().map_err(to_string)
+        }
+
+        // Saves the proposal under its own id. Used to move proposals into a new database.
+        pub fn put_proposal(&self, proposal: &Proposal::Proposal) -> StoreResult<()> {
+            let conn = self.conn.lock().map_err(to_string)?;
+            write_proposal(&conn, proposal)
+        }
+
+        // Saves the guild's settings. Used to move settings into a new database.
+        pub fn put_settings(&self, guild: GuildId, settings: &Settings::Settings) -> StoreResult<()> {
+            let conn = self.conn.lock().map_err(to_string)?;
+            write_settings(&conn, guild, settings)
+        }
+    }
+
+    impl TradeStore for SqliteStore {
+        fn get(&self, user: UserId) -> StoreResult<Option<Trader::Trader>> {
+            let conn = self.conn.lock().map_err(to_string)?;
+            load_trader(&conn, user)
+        }
+
+        fn update(&self, user: UserId, create: bool, change: &mut dyn FnMut(&mut Trader::Trader)) -> StoreResult<bool> {
+            let mut conn = self.conn.lock().map_err(to_string)?;
+            let tx = conn.transaction().map_err(to_string)?;
+            let mut trader = match load_trader(&tx, user)? {
+                Some(t) => t,
+                None if create => Trader::new(),
+                None => return Ok(false),
+            };
+            change(&mut trader);
+            write_trader(&tx, user, &trader)?;
+            tx.commit().map_err(to_string)?;
+            Ok(true)
+        }
+
+        fn all(&self) -> StoreResult<Vec<(UserId, Trader::Trader)>> {
+            let conn = self.conn.lock().map_err(to_string)?;
+            load_traders(&conn)
+        }
+
+        fn public(&self) -> StoreResult<Vec<(UserId, String, Tradelist::Tradelist)>> {
+            let conn = self.conn.lock().map_err(to_string)?;
+            let mut stmt = conn
+                .prepare_cached("SELECT user_id, name FROM lists WHERE is_public = 1")
+                .map_err(to_string)?;
+            let lists = stmt
+                .query_map([], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?)))
+                .map_err(to_string)?
+                .collect::<Result<Vec<(i64, String)>, _>>()
+                .map_err(to_string)?;
+            let mut digest = Vec::new();
+            for (id, name) in lists {
+                let user = UserId(id as u64);
+                if let Some(trader) = load_trader(&conn, user)? {
+                    if let Ok(list) = trader.get(Some(&name)) {
+                        digest.push((user, name, list.clone()));
+                    }
+                }
+            }
+            Ok(digest)
+        }
+
+        fn who_has(&self, card_name: &str) -> StoreResult<Vec<(UserId, String, CardEntry::CardEntry)>> {
+            let conn = self.conn.lock().map_err(to_string)?;
+            let mut stmt = conn
+                .prepare_cached(
+                    "SELECT e.user_id, e.list, e.data FROM list_entries e
+                     JOIN lists l ON l.user_id = e.user_id AND l.name = e.list
+                     WHERE e.kind = ?1 AND e.name = ?2 AND l.is_public = 1",
+                )
+                .map_err(to_string)?;
+            let rows = stmt
+                .query_map(params![HAVE, card_name], |r| {
+                    Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?, r.get::<_, String>(2)?))
+                })
+                .map_err(to_string)?;
+            let mut digest = Vec::new();
+            for row in rows {
+                let (id, list, data) = row.map_err(to_string)?;
+                let entry: CardEntry::CardEntry = serde_json::from_str(&data).map_err(to_string)?;
+                digest.push((UserId(id as u64), list, entry));
+            }
+            Ok(digest)
+        }
+
+        fn profile(&self, user: UserId) -> StoreResult<Option<Profile::Profile>> {
+            let conn = self.conn.lock().map_err(to_string)?;
+            let profile: Option<String> = conn
+                .query_row("SELECT profile FROM traders WHERE user_id = ?1", params![user.0 as i64], |r| r.get(0))
+                .optional()
+                .map_err(to_string)?;
+            match profile {
+                Some(p) => serde_json::from_str(&p).map(Some).map_err(to_string),
+                None => Ok(None),
+            }
+        }
+
+        fn proposal(&self, id: u64) -> StoreResult<Option<Proposal::Proposal>> {
+            let conn = self.conn.lock().map_err(to_string)?;
+            load_proposal(&conn, id)
+        }
+
+        fn propose(&self, mut proposal: Proposal::Proposal) -> StoreResult<u64> {
+            let mut conn = self.conn.lock().map_err(to_string)?;
+            let tx = conn.transaction().map_err(to_string)?;
+            let last: i64 = tx
+                .query_row("SELECT coalesce(max(id), 0) FROM proposals", [], |r| r.get(0))
+                .map_err(to_string)?;
+            proposal.id = last as u64 + 1;
+            write_proposal(&tx, &proposal)?;
+            tx.commit().map_err(to_string)?;
+            Ok(proposal.id)
+        }
+
+        fn update_proposal(&self, id: u64, change: &mut dyn FnMut(&mut Proposal::Proposal)) -> StoreResult<bool> {
+            let mut conn = self.conn.lock().map_err(to_string)?;
+            let tx = conn.transaction().map_err(to_string)?;
+            let mut proposal = match load_proposal(&tx, id)? {
+                Some(p) => p,
+                None => return Ok(false),
+            };
+            change(&mut proposal);
+            write_proposal(&tx, &proposal)?;
+            tx.commit().map_err(to_string)?;
+            Ok(true)
+        }
+
+        fn update_trade(
+            &self,
+            id: u64,
+            change: &mut dyn FnMut(&mut Proposal::Proposal, &mut Trader::Trader, &mut Trader::Trader) -> bool,
+        ) -> StoreResult<bool> {
+            let mut conn = self.conn.lock().map_err(to_string)?;
+            let tx = conn.transaction().map_err(to_string)?;
+            let mut proposal = match load_proposal(&tx, id)? {
+                Some(p) => p,
+                None => return Ok(false),
+            };
+            let mut from = load_trader(&tx, proposal.from)?.unwrap_or_else(Trader::new);
+            let mut to = load_trader(&tx, proposal.to)?.unwrap_or_else(Trader::new);
+            if !change(&mut proposal, &mut from, &mut to) {
+                return Ok(false);
+            }
+            write_trader(&tx, proposal.from, &from)?;
+            write_trader(&tx, proposal.to, &to)?;
+            write_proposal(&tx, &proposal)?;
+            tx.commit().map_err(to_string)?;
+            Ok(true)
+        }
+
+        fn proposals(&self) -> StoreResult<Vec<Proposal::Proposal>> {
+            let conn = self.conn.lock().map_err(to_string)?;
+            query_proposals(&conn, "SELECT data FROM proposals ORDER BY id", &[])
+        }
+
+        fn proposals_for(&self, user: UserId) -> StoreResult<Vec<Proposal::Proposal>> {
+            let conn = self.conn.lock().map_err(to_string)?;
+            let id = user.0 as i64;
+            query_proposals(
+                &conn,
+                "SELECT data FROM proposals WHERE from_user = ?1 OR to_user = ?1 ORDER BY id",
+                &[&id],
+            )
+        }
+
+        fn settings(&self, guild: GuildId) -> StoreResult<Settings::Settings> {
+            let conn = self.conn.lock().map_err(to_string)?;
+            load_settings(&conn, guild)
+        }
+
+        fn update_settings(&self, guild: GuildId, change: &mut dyn FnMut(&mut Settings::Settings)) -> StoreResult<()> {
+            let mut conn = self.conn.lock().map_err(to_string)?;
+            let tx = conn.transaction().map_err(to_string)?;
+            let mut settings = load_settings(&tx, guild)?;
+            change(&mut settings);
+            write_settings(&tx, guild, &settings)?;
+            tx.commit().map_err(to_string)
+        }
+
+        fn all_settings(&self) -> StoreResult<Vec<(GuildId, Settings::Settings)>> {
+            let conn = self.conn.lock().map_err(to_string)?;
+            load_all_settings(&conn)
+        }
+
+        fn snapshot(&self) -> StoreResult<Contents> {
+            // Every write holds the connection, so nothing changes between the reads
+            let conn = self.conn.lock().map_err(to_string)?;
+            let proposals = query_proposals(&conn, "SELECT data FROM proposals ORDER BY id", &[])?;
+            Ok((load_traders(&conn)?, proposals, load_all_settings(&conn)?))
+        }
+
+        fn is_persistent(&self) -> bool {
+            true
+        }
+    }
+}
@@ -0,0 +1,131 @@
+pub mod Storage {
+
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::io::{self, Write};
+    use std::path::{Path, PathBuf};
+
+    use dashmap::DashMap;
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+    use serenity::model::id::{GuildId, UserId};
+    use serenity::prelude::*;
+
+    use crate::migrations::Migrations;
+    use crate::proposal::Proposal;
+    use crate::settings::Settings;
+    use crate::trade_store::TradeStore::TradeStore;
+    use crate::trader::Trader;
+
+    // What a snapshot holds: the traders, the trade proposals and each guild's settings.
+    pub type Loaded = (DashMap<UserId, Trader::Trader>, Vec<Proposal::Proposal>, Vec<(GuildId, Settings::Settings)>);
+
+    // Saves tradelists to a single json snapshot. Snapshots are written to a temporary file
+    // that is then renamed over the old snapshot, so a crash mid-write never leaves a
+    // partial file behind.
+    pub struct Storage {
+        path: PathBuf,
+        // The last snapshot that was written, used to skip writes when nothing changed
+        last_saved: Mutex<String>,
+    }
+
+    impl TypeMapKey for Storage {
+        type Value = std::sync::Arc<Storage>;
+    }
+
+    // What is written to disk. The version lets older snapshots be upgraded by Migrations.
+    // A BTreeMap keeps the snapshot in a stable order so unchanged data is recognized.
+    #[derive(Serialize, Deserialize)]
+    struct Snapshot {
+        version: u32,
+        traders: BTreeMap<UserId, Trader::Trader>,
+        #[serde(default)]
+        proposals: Vec<Proposal::Proposal>,
+        #[serde(default)]
+        guilds: BTreeMap<GuildId, Settings::Settings>,
+    }
+
+    fn invalid_data(e: String) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+
+    pub fn new(path: PathBuf) -> Storage {
+        Storage { path, last_saved: Mutex::new(String::new()) }
+    }
+
+    impl Storage {
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+
+        // Reads the snapshot and upgrades it to the current version. Unless this is a dry run,
+        // the old snapshot is backed up and the upgraded one is written in its place. Returns
+        // the upgraded snapshot and the migrations that were applied, or None if there is no
+        // snapshot yet.
+        pub fn migrate(&self, dry_run: bool) -> io::Result<Option<(Value, Vec<String>)>> {
+            let data = match fs::read_to_string(&self.path) {
+                Ok(d) => d,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            let data: Value = serde_json::from_str(&data)?;
+            let from = Migrations::snapshot_version(&data);
+            let (data, applied) = Migrations::migrate_snapshot(data).map_err(invalid_data)?;
+            // Never replace a snapshot with one that won't load
+            serde_json::from_value::<Snapshot>(data.clone())?;
+            if !applied.is_empty() && !dry_run {
+                fs::copy(&self.path, Migrations::backup_path(&self.path, from))?;
+                write_atomic(&self.path, serde_json::to_string(&data)?.as_bytes())?;
+            }
+            Ok(Some((data, applied)))
+        }
+
+        // Reads the snapshot, migrating it first if needed. A missing snapshot just means
+        // there is nothing saved yet.
+        pub async fn load(&self) -> io::Result<Loaded> {
+            let digest = DashMap::new();
+            let data = match self.migrate(false)? {
+                Some((data, applied)) => {
+                    for step in applied {
+                        println!("Migrated {} from {}", self.path.display(), step);
+                    }
+                    data
+                }
+                None => return Ok((digest, Vec::new(), Vec::new())),
+            };
+            let snapshot: Snapshot = serde_json::from_value(data)?;
+            *self.last_saved.lock().await = serde_json::to_string(&snapshot)?;
+            for (id, trader) in snapshot.traders {
+                digest.insert(id, trader);
+            }
+            Ok((digest, snapshot.proposals, snapshot.guilds.into_iter().collect()))
+        }
+
+        // Writes a snapshot of the tradelists, proposals and guild settings, unless it would be identical to
+        // the last one.
+        pub async fn save(&self, store: &dyn TradeStore) -> io::Result<()> {
+            let (traders, proposals, guilds) = store.snapshot().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            let traders = traders.into_iter().collect();
+            let guilds = guilds.into_iter().collect();
+            let snapshot = Snapshot { version: Migrations::SNAPSHOT_VERSION, traders, proposals, guilds };
+            let data = serde_json::to_string(&snapshot)?;
+            let mut last_saved = self.last_saved.lock().await;
+            if *last_saved == data {
+                return Ok(());
+            }
+            write_atomic(&self.path, data.as_bytes())?;
+            *last_saved = data;
+            Ok(())
+        }
+    }
+
+    pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        {
+            let mut file = fs::File::create(&tmp)?;
+            file.write_all(data)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, path)
+    }
+}
@@ -0,0 +1,275 @@
+pub mod TradeStore {
+
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, RwLock};
+
+    use dashmap::DashMap;
+    use serenity::model::id::{GuildId, UserId};
+    use serenity::prelude::*;
+
+    use crate::card_entry::CardEntry;
+    use crate::profile::Profile;
+    use crate::proposal::Proposal;
+    use crate::settings::Settings;
+    use crate::trader::Trader;
+    use crate::tradelist::Tradelist;
+
+    pub type StoreResult<T> = Result<T, String>;
+
+    // Every trader, proposal and guild's settings.
+    pub type Contents = (Vec<(UserId, Trader::Trader)>, Vec<Proposal::Proposal>, Vec<(GuildId, Settings::Settings)>);
+
+    // All reads and writes of traders and their lists go through a TradeStore so that where
+    // they are kept (memory, a SQLite database, ...) is up to the store.
+    pub trait TradeStore: Send + Sync {
+        fn get(&self, user: UserId) -> StoreResult<Option<Trader::Trader>>;
+
+        // Applies the change to the user's lists as a single update, so that two commands
+        // can't interleave their changes. If the user has no lists, a new trader is created
+        // first when create is true. Returns whether or not the change was applied.
+        fn update(&self, user: UserId, create: bool, change: &mut dyn FnMut(&mut Trader::Trader)) -> StoreResult<bool>;
+
+        // Every trader, public or not. Used for snapshots and migrations.
+        fn all(&self) -> StoreResult<Vec<(UserId, Trader::Trader)>>;
+
+        // Just the user's profile, for ranking the partners found by searches.
+        fn profile(&self, user: UserId) -> StoreResult<Option<Profile::Profile>> {
+            Ok(self.get(user)?.map(|t| t.profile().clone()))
+        }
+
+        // Every public list, with its owner and name.
+        fn public(&self) -> StoreResult<Vec<(UserId, String, Tradelist::Tradelist)>> {
+            let mut digest = Vec::new();
+            for (id, trader) in self.all()? {
+                for (name, list) in trader.lists().filter(|(_, l)| l.is_public()) {
+                    digest.push((id, name.clone(), list.clone()));
+                }
+            }
+            Ok(digest)
+        }
+
+        // Every have with the given card name that is in a public list, with the owner and
+        // the name of the list.
+        fn who_has(&self, card_name: &str) -> StoreResult<Vec<(UserId, String, CardEntry::CardEntry)>> {
+            let mut digest = Vec::new();
+            for (id, name, list) in self.public()? {
+                for have in list.haves().get(card_name) {
+                    digest.push((id, name.clone(), have.clone()));
+                }
+            }
+            Ok(digest)
+        }
+
+        fn proposal(&self, id: u64) -> StoreResult<Option<Proposal::Proposal>>;
+
+        // Saves a new proposal under the next free id, which is returned.
+        fn propose(&self, proposal: Proposal::Proposal) -> StoreResult<u64>;
+
+        // Applies the change to a proposal as a single update, like update does for traders.
+        // Returns whether or not the proposal exists.
+        fn update_proposal(&self, id: u64, change: &mut dyn FnMut(&mut Proposal::Proposal)) -> StoreResult<bool>;
+
+        // Applies the change to a proposal and the traders on both sides of it as a single
+        // update. Traders without lists start out empty. Nothing is saved unless the change
+        // returns true. Returns whether or not the change was saved.
+        fn update_trade(
+            &self,
+            id: u64,
+            change: &mut dyn FnMut(&mut Proposal::Proposal, &mut Trader::Trader, &mut Trader::Trader) -> bool,
+        ) -> StoreResult<bool>;
+
+        // Every proposal, in the order they were made. Used for snapshots and migrations.
+        fn proposals(&self) -> StoreResult<Vec<Proposal::Proposal>>;
+
+        // Every proposal that the user made or received.
+        fn proposals_for(&self, user: UserId) -> StoreResult<Vec<Proposal::Proposal>> {
+            Ok(self.proposals()?.into_iter().filter(|p| p.involves(user)).collect())
+        }
+
+        // The guild's settings. Guilds that never changed any get the defaults.
+        fn settings(&self, guild: GuildId) -> StoreResult<Settings::Settings>;
+
+        fn update_settings(&self, guild: GuildId, change: &mut dyn FnMut(&mut Settings::Settings)) -> StoreResult<()>;
+
+        // Every guild's settings. Used for snapshots and migrations.
+        fn all_settings(&self) -> StoreResult<Vec<(GuildId, Settings::Settings)>>;
+
+        // Everything in the store as of a single moment, so that a trade that is being
+        // completed shows up in both traders and the proposal or in none of them.
+        fn snapshot(&self) -> StoreResult<Contents>;
+
+        // True if the store saves itself and doesn't need to be snapshotted.
+        fn is_persistent(&self) -> bool {
+            false
+        }
+    }
+
+    pub struct Store;
+
+    impl TypeMapKey for Store {
+        type Value = Arc<dyn TradeStore>;
+    }
+
+    // Keeps every trader in memory. This is paired with Storage snapshots to survive
+    // restarts.
+    pub struct MemoryStore {
+        traders: DashMap<UserId, Trader::Trader>,
+        proposals: DashMap<u64, Proposal::Proposal>,
+        last_proposal: AtomicU64,
+        // Updates to a single trader share this lock, while trades, which change two
+        // traders at once, hold it alone
+        trades: RwLock<()>,
+        guilds: DashMap<GuildId, Settings::Settings>,
+    }
+
+    pub fn memory(
+        traders: DashMap<UserId, Trader::Trader>,
+        proposals: Vec<Proposal::Proposal>,
+        guilds: Vec<(GuildId, Settings::Settings)>,
+    ) -> MemoryStore {
+        let last_proposal = AtomicU64::new(proposals.iter().map(|p| p.id).max().unwrap_or(0));
+        let proposals = proposals.into_iter().map(|p| (p.id, p)).collect();
+        MemoryStore { traders, proposals, last_proposal, trades: RwLock::new(()), guilds: guilds.into_iter().collect() }
+    }
+
+    impl TradeStore for MemoryStore {
+        fn get(&self, user: UserId) -> StoreResult<Option<Trader::Trader>> {
+            Ok(self.traders.get(&user).map(|t| t.clone()))
+        }
+
+        fn update(&self, user: UserId, create: bool, change: &mut dyn FnMut(&mut Trader::Trader)) -> StoreResult<bool> {
+            let _shared = self.trades.read().map_err(|e| e.to_string())?;
+            if create {
+                change(&mut self.traders.entry(user).or_insert_with(Trader::new));
+                return Ok(true);
+            }
+            match self.traders.get_mut(&user) {
+                Some(mut trader) => {
+                    change(&mut trader);
+                    Ok(true)
+                }
+                None => Ok(false),
+            }
+        }
+
+        fn all(&self) -> StoreResult<Vec<(UserId, Trader::Trader)>> {
+            Ok(self.traders.iter().map(|t| (*t.key(), t.value().clone())).collect())
+        }
+
+        fn who_has(&self, card_name: &str) -> StoreResult<Vec<(UserId, String, CardEntry::CardEntry)>> {
+            let mut digest = Vec::new();
+            for trader in self.traders.iter() {
+                for (name, list) in trader.lists().filter(|(_, l)| l.is_public()) {
+                    for have in list.haves().get(card_name) {
+                        digest.push((*trader.key(), name.clone(), have.clone()));
+                    }
+                }
+            }
+            Ok(digest)
+        }
+
+        fn proposal(&self, id: u64) -> StoreResult<Option<Proposal::Proposal>> {
+            Ok(self.proposals.get(&id).map(|p| p.clone()))
+        }
+
+        fn propose(&self, mut proposal: Proposal::Proposal) -> StoreResult<u64> {
+            let _shared = self.trades.read().map_err(|e| e.to_string())?;
+            proposal.id = self.last_proposal.fetch_add(1, Ordering::SeqCst) + 1;
+            let id = proposal.id;
+            self.proposals.insert(id, proposal);
+            Ok(id)
+        }
+
+        fn update_proposal(&self, id: u64, change: &mut dyn FnMut(&mut Proposal::Proposal)) -> StoreResult<bool> {
+            let _shared = self.trades.read().map_err(|e| e.to_string())?;
+            match self.proposals.get_mut(&id) {
+                Some(mut proposal) => {
+                    change(&mut proposal);
+                    Ok(true)
+                }
+                None => Ok(false),
+            }
+        }
+
+        fn update_trade(
+            &self,
+            id: u64,
+            change: &mut dyn FnMut(&mut Proposal::Proposal, &mut Trader::Trader, &mut Trader::Trader) -> bool,
+        ) -> StoreResult<bool> {
+            let _alone = self.trades.write().map_err(|e| e.to_string())?;
+            let mut proposal = match self.proposal(id)? {
+                Some(p) => p,
+                None => return Ok(false),
+            };
+            let mut from = self.get(proposal.from)?.unwrap_or_else(Trader::new);
+            let mut to = self.get(proposal.to)?.unwrap_or_else(Trader::new);
+            if !change(&mut proposal, &mut from, &mut to) {
+                return Ok(false);
+            }
+            self.traders.insert(proposal.from, from);
+            self.traders.insert(proposal.to, to);
+            self.proposals.insert(id, proposal);
+            Ok(true)
+        }
+
+        fn proposals(&self) -> StoreResult<Vec<Proposal::Proposal>> {
+            let mut digest: Vec<Proposal::Proposal> = self.proposals.iter().map(|p| p.value().clone()).collect();
+            digest.sort_by_key(|p| p.id);
+            Ok(digest)
+        }
+
+        fn settings(&self, guild: GuildId) -> StoreResult<Settings::Settings> {
+            Ok(self.guilds.get(&guild).map(|s| s.clone()).unwrap_or_default())
+        }
+
+        fn update_settings(&self, guild: GuildId, change: &mut dyn FnMut(&mut Settings::Settings)) -> StoreResult<()> {
+            change(&mut self.guilds.entry(guild).or_default());
+            Ok(())
+        }
+
+        fn all_settings(&self) -> StoreResult<Vec<(GuildId, Settings::Settings)>> {
+            Ok(self.guilds.iter().map(|s| (*s.key(), s.value().clone())).collect())
+        }
+
+        fn snapshot(&self) -> StoreResult<Contents> {
+            // Holding the lock alone waits for every update in progress to finish
+            let _alone = self.trades.write().map_err(|e| e.to_string())?;
+            Ok((self.all()?, self.proposals()?, self.all_settings()?))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const FROM: UserId = UserId(1);
+        const TO: UserId = UserId(2);
+
+        fn store() -> MemoryStore {
+            let store = memory(DashMap::new(), Vec::new(), Vec::new());
+            store.update(FROM, true, &mut |_| {}).unwrap();
+            store.propose(Proposal::new(FROM, TO, Vec::new(), Vec::new(), None)).unwrap();
+            store
+        }
+
+        #[test]
+        fn trades_are_saved_whole_or_not_at_all() {
+            let store = store();
+            // A change that gives up partway through leaves everything as it was
+            let changed = store.update_trade(1, &mut |proposal, _, _| {
+                proposal.close(TO, Proposal::Status::Declined).unwrap();
+                false
+            });
+            assert_eq!(changed, Ok(false));
+            let (traders, proposals, _) = store.snapshot().unwrap();
+            assert_eq!(traders.len(), 1);
+            assert!(proposals[0].is_open());
+
+            let changed = store.update_trade(1, &mut |proposal, _, _| proposal.close(TO, Proposal::Status::Declined).is_ok());
+            assert_eq!(changed, Ok(true));
+            let (traders, proposals, _) = store.snapshot().unwrap();
+            assert_eq!(traders.len(), 2);
+            assert_eq!(proposals[0].status(), Proposal::Status::Declined);
+        }
+    }
+}
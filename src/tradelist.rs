@@ -1,28 +1,29 @@
 pub mod Tradelist {
 
-    use std::collections::HashMap;
-    use dashmap::DashMap;
-    
-    use serenity::model;
-    use serenity::prelude::*;
+    use std::fmt;
+    use serde::{Deserialize, Serialize};
+
     use serenity::utils::Colour;
-    
+
+    use crate::card_db::CardDB;
+    use crate::list_view::ListView;
+    use crate::pager::Pager;
     use crate::response::Response;
     use crate::card::Card;
     use crate::card_entry::CardEntry;
+    use crate::card_list::CardList;
     use crate::utils::Types::*;
 
+    #[derive(Clone, Serialize, Deserialize)]
     pub struct Tradelist {
-        cards: HashMap<String, Vec<CardEntry::CardEntry>>,
+        cards: CardList::CardList,
+        #[serde(default)]
+        wants: CardList::CardList,
         is_public: bool
     }
-    
-    impl TypeMapKey for Tradelist {
-        type Value = DashMap<model::id::UserId, Tradelist>;
-    }
-    
+
     pub fn new( ) -> Tradelist {
-        Tradelist { cards: HashMap::new(), is_public: false }
+        Tradelist { cards: CardList::new(), wants: CardList::new(), is_public: false }
     }
 
     impl Tradelist {
@@ -35,100 +36,89 @@ pub mod Tradelist {
             self.is_public = false;
         }
 
-        fn add_new_entry( &mut self, entry: CardEntry::CardEntry ) {
-            let mut new_vec: Vec<CardEntry::CardEntry> = Vec::new();
-            new_vec.push(entry.clone());
-            self.cards.insert(entry.card.get_name(), new_vec);
+        pub fn is_public(&self) -> bool {
+            self.is_public
         }
 
-        fn increase_entry( &mut self, entry: CardEntry::CardEntry ) {
-            for c in self.cards.get_mut(&entry.card.get_name()).unwrap() {
-                if c.card == entry.card {
-                    c.count += entry.count
-                }
-            }
-            self.cards
-                .get_mut(&entry.card.get_name())
-                .unwrap()
-                .push(entry);
-        }
-
-        fn decrease_entry( &mut self, entry: CardEntry::CardEntry ) {
-            let listing = self.cards.get_mut(&entry.card.get_name()).unwrap();
-            let mut i: usize = 0;
-            while i < listing.len() {
-                if listing[i].card == entry.card {
-                    listing[i].dec_count(&entry.count);
-                    listing.remove(i);
-                    if listing.len() == 0 {
-                        self.cards.remove(&entry.card.get_name());
-                    }
-                    break;
-                }
-                i += 1;
-            }
+        pub fn haves(&self) -> &CardList::CardList {
+            &self.cards
+        }
+
+        pub fn wants(&self) -> &CardList::CardList {
+            &self.wants
         }
 
         pub fn add_card( &mut self, entry: CardEntry::CardEntry ) {
-            if entry.count == 0 {
-                ()
-            }
+            self.cards.add(entry);
+        }
 
-            match self.cards.get(&entry.card.get_name()) {
-                None => self.add_new_entry(entry),
-                Some(_) => self.increase_entry(entry),
-            }
+        // Returns the number of copies that were actually removed.
+        pub fn remove_card( &mut self, entry: CardEntry::CardEntry ) -> CardCount {
+            self.cards.remove(&entry)
         }
 
-        pub fn remove_card( &mut self, entry: CardEntry::CardEntry ) {
-            if entry.count == 0 {
-                ()
-            }
+        // Removes copies like remove_card, returning the copies that were removed.
+        pub fn take_cards( &mut self, entry: &CardEntry::CardEntry ) -> Vec<CardEntry::CardEntry> {
+            self.cards.take(entry)
+        }
 
-            match self.cards.get(&entry.card.get_name()) {
-                None => (),
-                Some(_) => self.decrease_entry(entry),
-            }
+        // Replaces the haves, keeping any holds on copies that are in both. Returns the held
+        // copies that are gone.
+        pub fn replace_haves( &mut self, mut cards: CardList::CardList ) -> Vec<CardEntry::CardEntry> {
+            let lost = cards.copy_reservations(&self.cards);
+            self.cards = cards;
+            lost
+        }
+
+        pub fn reserve_cards( &mut self, entry: &CardEntry::CardEntry, count: CardCount ) -> CardCount {
+            self.cards.reserve(entry, count)
+        }
+
+        pub fn release_cards( &mut self, entry: &CardEntry::CardEntry, count: CardCount ) -> CardCount {
+            self.cards.release(entry, count)
         }
 
         pub fn contains_card( &self, card: Card::Card ) -> bool {
-            let mut digest: bool = false;
-            let card_name = card.get_name();
-            let entry = self.cards.get(&card_name);
-            if entry.is_none() {
-                ()
-            }
-            let listing = entry.unwrap();
-            for c in listing {
-                if c.card.matches(&card) {
-                    digest = true;
-                    break;
-                }
-            }
-            digest
-        }
-    
-        pub fn to_string( &self ) -> String {
-            let mut digest: String = String::from("");
-            for (key, val) in self.cards.iter() {
-                for c in val {
-                    digest.push_str( &c.to_string() );
-                    digest.push_str( "\n" );
-                }
-            }
-            digest
-        }
-        
-        pub fn get_embed( &self ) -> Response::EmbedSpoof {
-            let mut digest = Response::EmbedSpoof {
-                title: String::from("Your Tradelist:"),
-                colour: if self.is_public {Colour::FOOYOO} else {Colour::RED},
-                fields: Vec::new()
-            };
+            self.cards.contains(&card)
+        }
+
+        pub fn add_want( &mut self, entry: CardEntry::CardEntry ) {
+            self.wants.add(entry);
+        }
+
+        // Returns the number of copies that were actually removed.
+        pub fn remove_want( &mut self, entry: CardEntry::CardEntry ) -> CardCount {
+            self.wants.remove(&entry)
+        }
+
+        // Removes copies like remove_want, returning the copies that were removed.
+        pub fn take_wants( &mut self, entry: &CardEntry::CardEntry ) -> Vec<CardEntry::CardEntry> {
+            self.wants.take(entry)
+        }
+
+        // The list as embeds, filtered, sorted and grouped as the options ask.
+        pub fn get_pages( &self, db: &CardDB::CardDB, name: &str, options: &ListView::ViewOptions ) -> Vec<Response::EmbedSpoof> {
             let public_word = if self.is_public {String::from("public")} else {String::from("private")};
-            digest.fields.push( (format!("This tradelist is {}.", public_word), String::from("\u{200b}"), false) );
-            digest.fields.push( (String::from("Cards"), self.to_string(), true) );
-            digest
+            let mut groups = ListView::lines(db, &self.cards, "Haves", options);
+            groups.extend(ListView::lines(db, &self.wants, "Wants", options));
+            Pager::paginate(
+                &format!("Your Tradelist: {}", name),
+                if self.is_public {Colour::FOOYOO} else {Colour::RED},
+                Some(&format!("This tradelist is {}.", public_word)),
+                &groups,
+                options.page_size,
+            )
+        }
+    }
+
+    impl fmt::Display for Tradelist {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.cards)?;
+            if !self.wants.is_empty() {
+                writeln!(f, "Wants:")?;
+                write!(f, "{}", self.wants)?;
+            }
+            Ok(())
         }
     }
 }
@@ -0,0 +1,372 @@
+pub mod Trader {
+
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::card_entry::CardEntry;
+    use crate::collection::Collection;
+    use crate::profile::Profile;
+    use crate::tradelist::Tradelist;
+    use crate::utils::Types::*;
+
+    // The list that every trader starts with, and that older single-list data is moved into.
+    pub const DEFAULT_LIST: &str = "Main";
+    const MAX_NAME_LENGTH: usize = 40;
+
+    // Everything a user keeps with the bot. Traders can keep several named lists (e.g.
+    // "Modern staples" and "Bulk rares"), each with its own visibility. Commands that aren't
+    // given a list use the default one. A trader can also record their collection, in which
+    // case the surplus can fill the haves of one of their lists, and a profile that tells
+    // others where and how they trade.
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct Trader {
+        lists: BTreeMap<String, Tradelist::Tradelist>,
+        default: String,
+        #[serde(default)]
+        collection: Collection::Collection,
+        #[serde(default)]
+        profile: Profile::Profile,
+        // Held copies that dropped out of the linked list since the last time they were
+        // taken, so that the proposals holding them can be looked into
+        #[serde(skip)]
+        lost_holds: Vec<CardEntry::CardEntry>,
+    }
+
+    pub fn new() -> Trader {
+        from_tradelist(Tradelist::new())
+    }
+
+    // A trader whose only list is the given one.
+    pub fn from_tradelist(list: Tradelist::Tradelist) -> Trader {
+        let mut lists = BTreeMap::new();
+        lists.insert(String::from(DEFAULT_LIST), list);
+        Trader { lists, default: String::from(DEFAULT_LIST), collection: Collection::new(), profile: Profile::new(), lost_holds: Vec::new() }
+    }
+
+    // Rebuilds a trader from stored lists. The default list is created if it is missing.
+    pub fn from_lists(mut lists: BTreeMap<String, Tradelist::Tradelist>, default: String, collection: Collection::Collection) -> Trader {
+        lists.entry(default.clone()).or_insert_with(Tradelist::new);
+        Trader { lists, default, collection, profile: Profile::new(), lost_holds: Vec::new() }
+    }
+
+    // List names are shown back to users and are typed in quotes, so they can't be empty,
+    // overly long or contain quotes.
+    fn check_name(name: &str) -> Result<String, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            Err(String::from("A list needs a name."))
+        } else if name.chars().count() > MAX_NAME_LENGTH {
+            Err(format!("List names can be at most {} characters long.", MAX_NAME_LENGTH))
+        } else if name.contains('"') {
+            Err(String::from("List names can't contain quotes."))
+        } else {
+            Ok(name.to_string())
+        }
+    }
+
+    impl Trader {
+        pub fn lists(&self) -> impl Iterator<Item = (&String, &Tradelist::Tradelist)> {
+            self.lists.iter()
+        }
+
+        pub fn default_name(&self) -> &str {
+            &self.default
+        }
+
+        // Finds a list by name, ignoring case, and returns its name as it was created. No
+        // name means the default list.
+        pub fn resolve(&self, name: Option<&str>) -> Result<String, String> {
+            let name = match name {
+                Some(n) => n.trim(),
+                None => return Ok(self.default.clone()),
+            };
+            self.lists
+                .keys()
+                .find(|k| k.eq_ignore_ascii_case(name))
+                .cloned()
+                .ok_or_else(|| format!("You don't have a list called \"{}\".", name))
+        }
+
+        pub fn get(&self, name: Option<&str>) -> Result<&Tradelist::Tradelist, String> {
+            let name = self.resolve(name)?;
+            Ok(&self.lists[&name])
+        }
+
+        pub fn get_mut(&mut self, name: Option<&str>) -> Result<&mut Tradelist::Tradelist, String> {
+            let name = self.resolve(name)?;
+            Ok(self.lists.get_mut(&name).unwrap())
+        }
+
+        // Like get_mut, but refuses lists whose haves come from the collection, since any
+        // change to them would be overwritten.
+        pub fn get_haves_mut(&mut self, name: Option<&str>) -> Result<&mut Tradelist::Tradelist, String> {
+            let name = self.resolve(name)?;
+            if self.is_linked(&name) {
+                return Err(format!("The haves of \"{}\" come from your collection. Use '!collection' to change them.", name));
+            }
+            Ok(self.lists.get_mut(&name).unwrap())
+        }
+
+        pub fn is_public(&self) -> bool {
+            self.lists.values().any(|l| l.is_public())
+        }
+
+        pub fn create(&mut self, name: &str) -> Result<String, String> {
+            let name = check_name(name)?;
+            if let Ok(existing) = self.resolve(Some(&name)) {
+                return Err(format!("You already have a list called \"{}\".", existing));
+            }
+            self.lists.insert(name.clone(), Tradelist::new());
+            Ok(name)
+        }
+
+        // Returns the list's old name.
+        pub fn rename(&mut self, old: &str, new: &str) -> Result<String, String> {
+            let old = self.resolve(Some(old))?;
+            let new = check_name(new)?;
+            // Renaming is allowed to just change the capitalization
+            if let Ok(existing) = self.resolve(Some(&new)) {
+                if existing != old {
+                    return Err(format!("You already have a list called \"{}\".", existing));
+                }
+            }
+            let list = self.lists.remove(&old).unwrap();
+            self.lists.insert(new.clone(), list);
+            if self.is_linked(&old) {
+                self.collection.set_linked(Some(new.clone()));
+            }
+            if self.default == old {
+                self.default = new;
+            }
+            Ok(old)
+        }
+
+        // The default list can't be deleted, so a trader always has at least one list. Nor
+        // can a list with copies held by trade proposals, which would lose their cards.
+        pub fn delete(&mut self, name: &str) -> Result<Tradelist::Tradelist, String> {
+            let name = self.resolve(Some(name))?;
+            if name == self.default {
+                return Err(format!("\"{}\" is your default list. Make another list the default before deleting it.", name));
+            }
+            let held = self.lists[&name].haves().reserved_count();
+            if held > 0 {
+                return Err(format!("\"{}\" has {} copies held for trade proposals. Withdraw or cancel the proposals first.", name, held));
+            }
+            if self.is_linked(&name) {
+                self.collection.set_linked(None);
+            }
+            Ok(self.lists.remove(&name).unwrap())
+        }
+
+        pub fn set_default(&mut self, name: &str) -> Result<String, String> {
+            self.default = self.resolve(Some(name))?;
+            Ok(self.default.clone())
+        }
+
+        // Moves up to entry.count copies from one list to another, keeping all of their
+        // details. Returns the number of copies that were moved.
+        pub fn move_cards(&mut self, from: &str, to: &str, entry: &CardEntry::CardEntry, is_want: bool) -> Result<CardCount, String> {
+            let from = self.resolve(Some(from))?;
+            let to = self.resolve(Some(to))?;
+            if from == to {
+                return Err(String::from("Cards can only be moved between two different lists."));
+            }
+            if !is_want {
+                if let Some(linked) = [&from, &to].iter().find(|l| self.is_linked(l)) {
+                    return Err(format!("The haves of \"{}\" come from your collection. Use '!collection' to change them.", linked));
+                }
+            }
+            let source = self.lists.get_mut(&from).unwrap();
+            let taken = if is_want { source.take_wants(entry) } else { source.take_cards(entry) };
+            let count = taken.iter().map(|c| c.count).sum();
+            let target = self.lists.get_mut(&to).unwrap();
+            for c in taken {
+                if is_want {
+                    target.add_want(c);
+                } else {
+                    target.add_card(c);
+                }
+            }
+            Ok(count)
+        }
+
+        // Holds the given cards for a trade proposal, so they aren't matched or offered again.
+        // Either every card is held or, if some are missing, none are.
+        pub fn reserve_cards(&mut self, cards: &[CardEntry::CardEntry]) -> Result<(), String> {
+            let mut held = Vec::new();
+            let mut missing = Vec::new();
+            for entry in cards {
+                let count = self.lists.values_mut().fold(0, |count, l| count + l.reserve_cards(entry, entry.count - count));
+                if count < entry.count {
+                    missing.push(format!("{} {}", entry.count - count, entry.card));
+                }
+                let mut h = entry.clone();
+                h.update_count(count);
+                held.push(h);
+            }
+            if missing.is_empty() {
+                return Ok(());
+            }
+            self.release_cards(&held);
+            Err(missing.join(", "))
+        }
+
+        // Lets go of the holds on the given cards.
+        pub fn release_cards(&mut self, cards: &[CardEntry::CardEntry]) {
+            for entry in cards {
+                self.lists.values_mut().fold(0, |released, l| released + l.release_cards(entry, entry.count - released));
+            }
+        }
+
+        // Takes the given cards out of the haves, for cards that were traded away. Copies in
+        // the list linked to the collection are taken out of the collection. Fails if there
+        // aren't enough copies, in which case some of the cards may already be gone.
+        pub fn give_cards(&mut self, cards: &[CardEntry::CardEntry]) -> Result<(), String> {
+            let names: Vec<String> = self.lists.keys().cloned().collect();
+            for entry in cards {
+                let mut needed = entry.count;
+                for name in &names {
+                    let copies: Vec<CardEntry::CardEntry> = self.lists[name].haves().matching(entry).cloned().collect();
+                    for mut copy in copies {
+                        if needed == 0 {
+                            break;
+                        }
+                        // Copies held by other proposals stay
+                        let count = std::cmp::min(needed, copy.available());
+                        copy.update_count(count);
+                        needed -= count;
+                        if self.is_linked(name) {
+                            self.collection.remove(&copy);
+                            self.sync_collection();
+                        } else {
+                            self.lists.get_mut(name).unwrap().take_cards(&copy);
+                        }
+                    }
+                }
+                if needed > 0 {
+                    return Err(format!("{} {} short", needed, entry.card));
+                }
+            }
+            Ok(())
+        }
+
+        // Takes the wants that the received cards fill out of every list, and returns them.
+        // The cards are added to the collection if asked.
+        pub fn receive_cards(&mut self, cards: &[CardEntry::CardEntry], to_collection: bool) -> Vec<CardEntry::CardEntry> {
+            let mut digest = Vec::new();
+            for entry in cards {
+                let mut left = entry.count;
+                for list in self.lists.values_mut() {
+                    let wants: Vec<CardEntry::CardEntry> = list.wants().iter().filter(|w| entry.satisfies(w)).cloned().collect();
+                    for mut want in wants {
+                        if left == 0 {
+                            break;
+                        }
+                        let count = std::cmp::min(left, want.count);
+                        want.update_count(count);
+                        left -= count;
+                        digest.extend(list.take_wants(&want));
+                    }
+                }
+                if to_collection {
+                    self.collection.add(entry.clone());
+                }
+            }
+            if to_collection {
+                self.sync_collection();
+            }
+            digest
+        }
+
+        pub fn profile(&self) -> &Profile::Profile {
+            &self.profile
+        }
+
+        pub fn profile_mut(&mut self) -> &mut Profile::Profile {
+            &mut self.profile
+        }
+
+        pub fn collection(&self) -> &Collection::Collection {
+            &self.collection
+        }
+
+        pub fn is_linked(&self, name: &str) -> bool {
+            self.collection.linked().map_or(false, |l| l == name)
+        }
+
+        // Changes the collection and then refreshes the linked list's haves.
+        pub fn update_collection<F: FnOnce(&mut Collection::Collection)>(&mut self, change: F) {
+            change(&mut self.collection);
+            self.sync_collection();
+        }
+
+        // Makes the list's haves the collection's surplus from now on. Whatever haves the
+        // list had are replaced.
+        pub fn link_collection(&mut self, name: Option<&str>) -> Result<String, String> {
+            let name = self.resolve(name)?;
+            self.collection.set_linked(Some(name.clone()));
+            self.sync_collection();
+            Ok(name)
+        }
+
+        // Stops updating the linked list. Its haves are left as they are.
+        pub fn unlink_collection(&mut self) -> Option<String> {
+            let linked = self.collection.linked().cloned();
+            self.collection.set_linked(None);
+            linked
+        }
+
+        // Held copies that dropped out of the linked list since this was last called.
+        pub fn take_lost_holds(&mut self) -> Vec<CardEntry::CardEntry> {
+            std::mem::take(&mut self.lost_holds)
+        }
+
+        fn sync_collection(&mut self) {
+            let surplus = self.collection.surplus();
+            if let Some(list) = self.collection.linked().and_then(|l| self.lists.get_mut(l)) {
+                let lost = list.replace_haves(surplus);
+                self.lost_holds.extend(lost);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::card::Card;
+        use crate::card_db::CardDB;
+        use crate::collection::Collection::parse_rule;
+
+        fn bolts(count: CardCount) -> CardEntry::CardEntry {
+            let db = CardDB::sample();
+            CardEntry::new(count, Card::new(db.get_card(&String::from("Lightning Bolt")).unwrap().clone(), String::new()))
+        }
+
+        #[test]
+        fn lists_with_held_copies_cant_be_deleted() {
+            let mut trader = new();
+            trader.create("Spare").unwrap();
+            trader.get_haves_mut(Some("Spare")).unwrap().add_card(bolts(4));
+            trader.reserve_cards(&[bolts(2)]).unwrap();
+            assert!(trader.delete("spare").is_err());
+            trader.release_cards(&[bolts(2)]);
+            assert_eq!(trader.delete("spare").map(|l| l.haves().card_count()), Ok(4));
+        }
+
+        #[test]
+        fn holds_lost_from_the_linked_list_are_reported() {
+            let mut trader = new();
+            trader.update_collection(|c| c.add(bolts(4)));
+            trader.link_collection(None).unwrap();
+            trader.reserve_cards(&[bolts(3)]).unwrap();
+            assert!(trader.take_lost_holds().is_empty());
+            let rule = parse_rule(&CardDB::sample(), "2").unwrap();
+            trader.update_collection(|c| c.add_rule(rule));
+            let lost = trader.take_lost_holds();
+            assert_eq!(lost.iter().map(|c| c.count).sum::<CardCount>(), 1);
+            assert!(trader.take_lost_holds().is_empty());
+            assert_eq!(trader.get(None).unwrap().haves().reserved_count(), 2);
+        }
+    }
+}